@@ -0,0 +1,445 @@
+//! Morphs objects from asynchronous servers (e.g., hyper or axum handlers).
+//!
+//! Morphing looks up the objects a page fetches as it builds the page's
+//! graph, which would block the executor if the lookups wait on a backend.
+//! Instead, `prefetch` looks them up ahead with an `AsyncObjectLookup`,
+//! concurrently, and the page is then morphed from memory, which does not
+//! block. `morph_html_async` and `morph_object_async` do both; any other
+//! entry point (e.g., `morph_html_cached`) can be called with the
+//! `PrefetchedLookup` returned by `prefetch`.
+//!
+//! This module requires the `async` feature.
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use auth::ParamAuth;
+use distribution::SamplingConfig;
+use graph::is_document_kind;
+use lookup::ObjectLookup;
+use morphing::{morph_html, morph_object_with_rng};
+use objects::*;
+use parsing::{parse_objects, resolve_uri};
+use placement::Placement;
+
+/// The future result of looking up an object asynchronously.
+pub type LookupFuture<'a, T> = Pin<Box<dyn Future<Output = Option<T>> + Send + 'a>>;
+
+/// Looks up asynchronously the objects referenced by a page (see
+/// `ObjectLookup`).
+pub trait AsyncObjectLookup {
+    /// Returns the size of the object at `uri`, or None if there is no such
+    /// object.
+    fn object_size(&self, uri: &str) -> LookupFuture<'_, usize>;
+
+    /// Returns the content of the object at `uri`, or None if there is no
+    /// such object.
+    fn object_content(&self, uri: &str) -> LookupFuture<'_, Vec<u8>>;
+}
+
+/// Looks up the objects fetched when loading a page, as they were when
+/// they were prefetched.
+#[derive(Default)]
+pub struct PrefetchedLookup {
+    sizes: HashMap<String, usize>,
+    contents: HashMap<String, Vec<u8>>,
+}
+
+impl ObjectLookup for PrefetchedLookup {
+    fn object_size(&self, uri: &str) -> Option<usize> {
+        self.sizes.get(uri).cloned()
+    }
+
+    fn object_content(&self, uri: &str) -> Option<Vec<u8>> {
+        self.contents.get(uri).cloned()
+    }
+}
+
+/// Looks up the objects fetched when loading a document (see `prefetch`).
+pub struct Prefetch<'a, L: AsyncObjectLookup + 'a> {
+    lookup: &'a L,
+    prefetched: PrefetchedLookup,
+    /// Paths of the objects looked up so far.
+    paths: HashSet<String>,
+    pending: Vec<PendingLookup<'a>>,
+}
+
+/// Looks up the objects fetched when loading `document` with `lookup`:
+/// the size of each object, and the content of documents, whose objects
+/// are looked up in turn.
+///
+/// # Returns
+/// A future of the lookup of these objects, which `PageGraph::build` needs
+/// to build the document's graph.
+pub fn prefetch<'a, L: AsyncObjectLookup>(document: &Object, lookup: &'a L) -> Prefetch<'a, L> {
+    let path = document
+        .uri
+        .as_ref()
+        .and_then(|uri| resolve_uri("/", uri))
+        .unwrap_or_else(|| "/".to_string());
+    let mut prefetch = Prefetch {
+        lookup,
+        prefetched: PrefetchedLookup::default(),
+        paths: HashSet::new(),
+        pending: Vec::new(),
+    };
+    prefetch.paths.insert(path.clone());
+    prefetch.lookup_refs(document, &path);
+    prefetch
+}
+
+impl<'a, L: AsyncObjectLookup> Prefetch<'a, L> {
+    /// Starts looking up the objects referenced by `document`, at `path`,
+    /// which are not looked up yet.
+    fn lookup_refs(&mut self, document: &Object, path: &str) {
+        for reference in parse_objects(document) {
+            let path = match reference
+                .uri
+                .as_ref()
+                .and_then(|uri| resolve_uri(path, uri))
+            {
+                Some(path) => path,
+                None => continue,
+            };
+            if self.paths.insert(path.clone()) {
+                self.pending.push(PendingLookup {
+                    state: LookupState::Size(self.lookup.object_size(&path)),
+                    kind: reference.kind,
+                    path,
+                });
+            }
+        }
+    }
+}
+
+impl<'a, L: AsyncObjectLookup> Future for Prefetch<'a, L> {
+    type Output = PrefetchedLookup;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<PrefetchedLookup> {
+        let prefetch = self.get_mut();
+        let mut i = 0;
+        while i < prefetch.pending.len() {
+            let found = match prefetch.pending[i].poll(prefetch.lookup, cx) {
+                Poll::Ready(found) => found,
+                Poll::Pending => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let pending = prefetch.pending.swap_remove(i);
+            let (size, content) = match found {
+                Some(found) => found,
+                None => continue,
+            };
+            if is_document_kind(pending.kind) {
+                let document = Object {
+                    kind: pending.kind,
+                    content,
+                    size,
+                    uri: Some(pending.path.clone()),
+                    position: None,
+                    target_size: None,
+                    candidate_set: None,
+                };
+                prefetch.lookup_refs(&document, &pending.path);
+                prefetch
+                    .prefetched
+                    .contents
+                    .insert(pending.path.clone(), document.content);
+            }
+            prefetch.prefetched.sizes.insert(pending.path, size);
+        }
+
+        if prefetch.pending.is_empty() {
+            Poll::Ready(std::mem::take(&mut prefetch.prefetched))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// The lookup of an object: first its size, then its content if it is a
+/// document.
+struct PendingLookup<'a> {
+    state: LookupState<'a>,
+    kind: ObjectKind,
+    path: String,
+}
+
+enum LookupState<'a> {
+    Size(LookupFuture<'a, usize>),
+    Content(usize, LookupFuture<'a, Vec<u8>>),
+}
+
+impl<'a> PendingLookup<'a> {
+    /// Polls the lookup of the object.
+    ///
+    /// # Returns
+    /// The object's size and, for documents, its content, or None if there
+    /// is no such object.
+    fn poll<L: AsyncObjectLookup>(
+        &mut self,
+        lookup: &'a L,
+        cx: &mut Context,
+    ) -> Poll<Option<(usize, Vec<u8>)>> {
+        loop {
+            let state = match self.state {
+                LookupState::Size(ref mut future) => match future.as_mut().poll(cx) {
+                    Poll::Ready(Some(size)) if is_document_kind(self.kind) => {
+                        LookupState::Content(size, lookup.object_content(&self.path))
+                    }
+                    Poll::Ready(found) => return Poll::Ready(found.map(|size| (size, Vec::new()))),
+                    Poll::Pending => return Poll::Pending,
+                },
+                LookupState::Content(size, ref mut future) => {
+                    return future
+                        .as_mut()
+                        .poll(cx)
+                        .map(|content| Some((size, content.unwrap_or_default())))
+                }
+            };
+            self.state = state;
+        }
+    }
+}
+
+/// A future which applies a function to the output of another.
+struct Then<F, G> {
+    future: F,
+    then: Option<G>,
+}
+
+impl<F: Future + Unpin, G: FnOnce(F::Output) -> T + Unpin, T> Future for Then<F, G> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.future).poll(cx) {
+            Poll::Ready(output) => {
+                let then = this.then.take().expect("Polled after completion");
+                Poll::Ready(then(output))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Morphs an HTML page as `morph_html` does, looking up the objects it
+/// fetches with `lookup`, without blocking.
+///
+/// # Returns
+/// A future of the target size of the HTML page, which the caller should
+/// pad it to.
+pub fn morph_html_async<'a, R: Rng, L: AsyncObjectLookup>(
+    rng: &'a mut R,
+    html: &'a mut Object,
+    lookup: &'a L,
+    placement: Placement,
+    config: &'a SamplingConfig,
+    auth: Option<&'a ParamAuth>,
+) -> impl Future<Output = Result<usize, ()>> + 'a {
+    Then {
+        future: prefetch(html, lookup),
+        then: Some(move |lookup: PrefetchedLookup| {
+            morph_html(rng, html, &lookup, placement, config, auth)
+        }),
+    }
+}
+
+/// Morphs an object as `morph_object_with_rng` does, looking up the objects
+/// it references with `lookup`, without blocking.
+///
+/// # Returns
+/// A future of the morphed object.
+pub fn morph_object_async<'a, R: Rng, L: AsyncObjectLookup>(
+    rng: &'a mut R,
+    object: &'a [u8],
+    request: &'a str,
+    lookup: &'a L,
+    placement: Placement,
+    config: &'a SamplingConfig,
+    auth: Option<&'a ParamAuth>,
+) -> impl Future<Output = Object> + 'a {
+    Then {
+        future: prefetch(&Object::from(object, request), lookup),
+        then: Some(move |lookup: PrefetchedLookup| {
+            morph_object_with_rng(rng, object, request, &lookup, placement, config, auth)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use std::sync::{Arc, Mutex};
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    /// Looks up objects in memory, each after yielding once to the executor,
+    /// and records the lookups.
+    struct YieldingLookup {
+        objects: HashMap<String, Vec<u8>>,
+        lookups: Mutex<Vec<String>>,
+    }
+
+    struct Yield<T> {
+        value: Option<T>,
+        yielded: bool,
+    }
+
+    impl<T: Unpin> Future for Yield<T> {
+        type Output = Option<T>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+            let this = self.get_mut();
+            if !this.yielded {
+                this.yielded = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(this.value.take())
+        }
+    }
+
+    impl AsyncObjectLookup for YieldingLookup {
+        fn object_size(&self, uri: &str) -> LookupFuture<'_, usize> {
+            self.lookups.lock().unwrap().push(format!("size {}", uri));
+            let value = self.objects.object_size(uri);
+            Box::pin(Yield {
+                value,
+                yielded: false,
+            })
+        }
+
+        fn object_content(&self, uri: &str) -> LookupFuture<'_, Vec<u8>> {
+            self.lookups
+                .lock()
+                .unwrap()
+                .push(format!("content {}", uri));
+            let value = self.objects.object_content(uri);
+            Box::pin(Yield {
+                value,
+                yielded: false,
+            })
+        }
+    }
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    fn generate_lookup() -> YieldingLookup {
+        let mut objects = HashMap::new();
+        objects.insert(
+            "/css/style.css".to_string(),
+            b"@import 'a.css'; body { background: url(../img/bg.png); }".to_vec(),
+        );
+        objects.insert("/css/a.css".to_string(), b"p {}".to_vec());
+        objects.insert("/img/bg.png".to_string(), vec![0u8; 500]);
+        objects.insert("/a.png".to_string(), vec![0u8; 1000]);
+        YieldingLookup {
+            objects,
+            lookups: Mutex::new(Vec::new()),
+        }
+    }
+
+    static HTML: &[u8] = b"<html><head><link rel=\"stylesheet\" href=\"css/style.css\"></head>\
+                           <body><img src=\"a.png\"><img src=\"/a.png\">\
+                           <img src=\"missing.png\"></body></html>";
+
+    #[test]
+    fn test_prefetch() {
+        let lookup = generate_lookup();
+        let html = Object::from(HTML, "GET /index.html HTTP/1.1");
+        let prefetched = block_on(prefetch(&html, &lookup));
+
+        assert_eq!(prefetched.object_size("/css/style.css"), Some(57));
+        assert_eq!(prefetched.object_size("/css/a.css"), Some(4));
+        assert_eq!(prefetched.object_size("/img/bg.png"), Some(500));
+        assert_eq!(prefetched.object_size("/a.png"), Some(1000));
+        assert_eq!(prefetched.object_size("/missing.png"), None);
+        assert_eq!(
+            prefetched.object_content("/css/a.css"),
+            Some(b"p {}".to_vec())
+        );
+
+        // Each object is looked up once, and only documents' content is.
+        let mut lookups = lookup.lookups.into_inner().unwrap();
+        lookups.sort();
+        assert_eq!(
+            lookups,
+            vec![
+                "content /css/a.css",
+                "content /css/style.css",
+                "size /a.png",
+                "size /css/a.css",
+                "size /css/style.css",
+                "size /img/bg.png",
+                "size /missing.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_morph_html_async() {
+        let lookup = generate_lookup();
+        let config = SamplingConfig::default();
+
+        // Morphing is the same as from a synchronous lookup.
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let mut expected = Object::from(HTML, "GET /index.html HTTP/1.1");
+        let expected_size = morph_html(
+            &mut rng,
+            &mut expected,
+            &lookup.objects,
+            Placement::default(),
+            &config,
+            None,
+        );
+
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let mut html = Object::from(HTML, "GET /index.html HTTP/1.1");
+        let target_size = block_on(morph_html_async(
+            &mut rng,
+            &mut html,
+            &lookup,
+            Placement::default(),
+            &config,
+            None,
+        ));
+        assert_eq!(target_size, expected_size);
+        assert_eq!(html.content, expected.content);
+
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let object = block_on(morph_object_async(
+            &mut rng,
+            HTML,
+            "GET /index.html HTTP/1.1",
+            &lookup,
+            Placement::default(),
+            &config,
+            None,
+        ));
+        assert_eq!(object.content.len(), expected_size.unwrap());
+    }
+}
@@ -0,0 +1,385 @@
+//! Authenticates the parameters appended to the references to objects.
+//!
+//! Anyone may request an object with any `alpaca-padding` parameter, and
+//! have the server pad it to any size. Morphing may instead append an
+//! expiry time to the parameters, and a MAC (HMAC-SHA256, keyed with a
+//! server secret) of them and of the object's path, which the server
+//! verifies before padding the object.
+//!
+//! Parameters may also be encrypted (with ChaCha20-Poly1305) into an opaque
+//! token, so that those who see the URLs do not learn the target sizes.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use parsing::{
+    parse_param, parse_request_uri, parse_target_size, EXPIRES_PARAM, MAC_PARAM, TOKEN_PARAM,
+};
+
+// Bytes of the MAC, a truncated HMAC-SHA256.
+const MAC_LEN: usize = 16;
+// Bytes of the nonce and of the tag of a token.
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+// Maximum number of digits of an expiry time.
+const MAX_EXPIRES_DIGITS: usize = 20;
+
+/// What to do with a target size which is not authenticated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unauthenticated {
+    /// Do not pad the object.
+    Refuse,
+    /// Pad the object to at most the given size.
+    Clamp(usize),
+}
+
+/// Authenticates the parameters of the references to objects.
+pub struct ParamAuth {
+    key: Vec<u8>,
+    ttl: Duration,
+    unauthenticated: Unauthenticated,
+    encrypted: bool,
+}
+
+impl ParamAuth {
+    /// Construct a ParamAuth with the secret `key`, whose parameters are
+    /// valid for `ttl`, and which handles target sizes which are not
+    /// authenticated as `unauthenticated` tells.
+    pub fn new(key: &[u8], ttl: Duration, unauthenticated: Unauthenticated) -> ParamAuth {
+        ParamAuth {
+            key: key.to_vec(),
+            ttl,
+            unauthenticated,
+            encrypted: false,
+        }
+    }
+
+    /// Construct a ParamAuth as `new` does, which encrypts the parameters
+    /// into an opaque `alpaca-token` parameter, which only the server can
+    /// read.
+    pub fn new_encrypted(key: &[u8], ttl: Duration, unauthenticated: Unauthenticated) -> ParamAuth {
+        ParamAuth {
+            encrypted: true,
+            ..ParamAuth::new(key, ttl, unauthenticated)
+        }
+    }
+
+    /// Returns how long parameters protected from now on are valid for, at
+    /// least: their expiry time is in whole seconds.
+    pub(crate) fn get_min_validity(&self) -> Duration {
+        Duration::from_secs(self.ttl.as_secs().saturating_sub(1))
+    }
+
+    /// Returns the parameters `params`, to append to the references to the
+    /// object at `path`, as authenticated (and encrypted, if so).
+    pub(crate) fn protect(&self, path: &str, params: &str) -> String {
+        let expires = get_time() + self.ttl.as_secs();
+        let params = format!("{}&{}={}", params, EXPIRES_PARAM, expires);
+        if self.encrypted {
+            return format!("{}={}", TOKEN_PARAM, self.encrypt(path, &params));
+        }
+
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let signed = format!("{}{}{}", path, separator, params);
+        let mac = self.get_mac(b"mac", &[signed.as_bytes()]);
+        let mac = mac[..MAC_LEN]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        format!("{}&{}={}", params, MAC_PARAM, mac)
+    }
+
+    /// Returns the request target (URI) of an HTTP request, with its
+    /// parameters in the clear, if they are authenticated and have not
+    /// expired.
+    pub fn open(&self, request: &str) -> Option<String> {
+        let uri = parse_request_uri(request);
+        let uri = uri.split('#').next().unwrap_or("");
+        let opened = if self.encrypted {
+            self.decrypt(uri)?
+        } else {
+            self.verify_mac(uri)?
+        };
+
+        let expires = parse_param(&opened, EXPIRES_PARAM)?.parse::<u64>().ok()?;
+        if expires <= get_time() {
+            return None;
+        }
+        Some(opened)
+    }
+
+    /// Returns whether the parameters of an HTTP request are authenticated
+    /// and have not expired.
+    pub fn verify(&self, request: &str) -> bool {
+        self.open(request).is_some()
+    }
+
+    /// Parses the target size of an object from its HTTP request, if it is
+    /// authenticated (see `verify`), or as the policy for unauthenticated
+    /// target sizes tells.
+    pub fn parse_target_size(&self, request: &str) -> Option<usize> {
+        if let Some(opened) = self.open(request) {
+            return parse_target_size(&opened);
+        }
+        let target_size = parse_target_size(request)?;
+        match self.unauthenticated {
+            Unauthenticated::Refuse => None,
+            Unauthenticated::Clamp(max) => Some(target_size.min(max)),
+        }
+    }
+
+    /// Returns the URI if its MAC is valid.
+    fn verify_mac(&self, uri: &str) -> Option<String> {
+        let mac_start = uri.rfind(&format!("&{}=", MAC_PARAM))?;
+        let hex = &uri[mac_start + 2 + MAC_PARAM.len()..];
+        if hex.len() != 2 * MAC_LEN {
+            return None;
+        }
+        let mac = (0..MAC_LEN)
+            .map(|i| u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+
+        let signed = &uri[..mac_start];
+        let expected = self.get_mac(b"mac", &[signed.as_bytes()]);
+        // Compare in constant time.
+        let diff = mac
+            .iter()
+            .zip(&expected[..MAC_LEN])
+            .fold(0, |diff, (a, b)| diff | (a ^ b));
+        if diff != 0 {
+            return None;
+        }
+        Some(signed.to_string())
+    }
+
+    /// Encrypts `params`, appended to the references to the object at
+    /// `path`, into a token.
+    ///
+    /// The nonce is a MAC of the path and parameters, so that encrypting
+    /// different parameters never reuses a nonce.
+    fn encrypt(&self, path: &str, params: &str) -> String {
+        let nonce = self.get_mac(b"nonce", &[path.as_bytes(), params.as_bytes()]);
+        let nonce = Nonce::from_slice(&nonce[..NONCE_LEN]);
+        let payload = Payload {
+            msg: params.as_bytes(),
+            aad: path.as_bytes(),
+        };
+        let ciphertext = self
+            .get_cipher()
+            .encrypt(nonce, payload)
+            .expect("ChaCha20-Poly1305 encrypts messages of any size");
+
+        let mut token = nonce.to_vec();
+        token.extend(ciphertext);
+        URL_SAFE_NO_PAD.encode(token)
+    }
+
+    /// Returns the URI with the parameters of its token in the clear, if it
+    /// is valid.
+    fn decrypt(&self, uri: &str) -> Option<String> {
+        let token_start = uri.rfind(&format!("{}=", TOKEN_PARAM))?;
+        let path = uri.get(..token_start.checked_sub(1)?)?;
+        if !uri[token_start - 1..].starts_with(['?', '&']) {
+            return None;
+        }
+        let token = URL_SAFE_NO_PAD
+            .decode(&uri[token_start + 1 + TOKEN_PARAM.len()..])
+            .ok()?;
+        if token.len() < NONCE_LEN + TAG_LEN {
+            return None;
+        }
+
+        let payload = Payload {
+            msg: &token[NONCE_LEN..],
+            aad: path.as_bytes(),
+        };
+        let params = self
+            .get_cipher()
+            .decrypt(Nonce::from_slice(&token[..NONCE_LEN]), payload)
+            .ok()?;
+        let params = String::from_utf8(params).ok()?;
+        let separator = if path.contains('?') { '&' } else { '?' };
+        Some(format!("{}{}{}", path, separator, params))
+    }
+
+    fn get_cipher(&self) -> ChaCha20Poly1305 {
+        let key = self.get_mac(b"key", &[]);
+        ChaCha20Poly1305::new(Key::from_slice(&key))
+    }
+
+    /// Returns the HMAC-SHA256 of `data`, keyed with the secret, for use
+    /// `label` (so that different uses of the secret are independent).
+    fn get_mac(&self, label: &[u8], data: &[&[u8]]) -> Vec<u8> {
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(label);
+        for data in data {
+            // Prefix each part with its length, so that no two inputs are
+            // hashed alike.
+            mac.update(&(data.len() as u64).to_be_bytes());
+            mac.update(data);
+        }
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+/// Parses the target size of an object from its HTTP request, verifying it
+/// with `auth` if any.
+pub(crate) fn get_target_size(request: &str, auth: Option<&ParamAuth>) -> Option<usize> {
+    match auth {
+        Some(auth) => auth.parse_target_size(request),
+        None => parse_target_size(request),
+    }
+}
+
+/// Returns the parameters `params`, to append to the references to the
+/// object at `path`, as authenticated with `auth`, if any.
+pub(crate) fn protect_params(path: &str, params: String, auth: Option<&ParamAuth>) -> String {
+    match auth {
+        Some(auth) => auth.protect(path, &params),
+        None => params,
+    }
+}
+
+/// Returns the request target (URI) of an HTTP request, with its parameters
+/// in the clear, if they are authenticated by `auth`, if any.
+pub(crate) fn open_request(request: &str, auth: Option<&ParamAuth>) -> Option<String> {
+    match auth {
+        Some(auth) => auth.open(request),
+        None => Some(parse_request_uri(request).to_string()),
+    }
+}
+
+/// Returns an upper bound to the length of parameters of length at most
+/// `params_len`, once authenticated (and encrypted) with `auth`, if any.
+pub(crate) fn get_auth_params_bound(auth: Option<&ParamAuth>, params_len: usize) -> usize {
+    // "&alpaca-expires=" followed by the expiry time.
+    let expires_len = 2 + EXPIRES_PARAM.len() + MAX_EXPIRES_DIGITS;
+    match auth {
+        // "alpaca-token=" followed by the nonce, encrypted parameters and
+        // tag, in base64.
+        Some(auth) if auth.encrypted => {
+            1 + TOKEN_PARAM.len() + get_base64_len(NONCE_LEN + params_len + expires_len + TAG_LEN)
+        }
+        // "&alpaca-mac=" followed by the MAC in hexadecimal.
+        Some(_) => params_len + expires_len + 2 + MAC_PARAM.len() + 2 * MAC_LEN,
+        None => params_len,
+    }
+}
+
+/// Returns an upper bound to how much parameters grow, once authenticated
+/// (and encrypted) with `auth`, if any, when they grow by `growth`.
+pub(crate) fn get_auth_growth_bound(auth: Option<&ParamAuth>, growth: usize) -> usize {
+    match auth {
+        Some(auth) if auth.encrypted => get_base64_len(growth),
+        _ => growth,
+    }
+}
+
+/// Returns the length of `len` bytes in unpadded base64.
+fn get_base64_len(len: usize) -> usize {
+    (4 * len).div_ceil(3)
+}
+
+/// Returns the current time, in seconds since the Unix epoch.
+fn get_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protect(auth: &ParamAuth, path: &str, params: &str) -> String {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        format!("{}{}{}", path, separator, auth.protect(path, params))
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let auth = ParamAuth::new(b"secret", Duration::from_secs(60), Unauthenticated::Refuse);
+        let params = "alpaca-padding=300";
+        let uri = protect(&auth, "/img.png", params);
+        assert!(auth.verify(&uri));
+        assert!(auth.verify(&format!("GET {} HTTP/1.1", uri)));
+        assert_eq!(auth.parse_target_size(&uri), Some(300));
+        let bound = get_auth_params_bound(Some(&auth), params.len());
+        assert!(uri.len() - "/img.png?".len() <= bound);
+
+        // Existing query parameters are authenticated too.
+        let signed = protect(&auth, "/img.png?v=1", params);
+        assert!(auth.verify(&signed));
+        assert!(!auth.verify(&signed.replace("v=1", "v=2")));
+
+        // Tampered, unauthenticated, or differently keyed parameters are
+        // refused.
+        assert!(!auth.verify(&uri.replace("300", "900")));
+        assert!(!auth.verify(&uri.replace("/img.png", "/big.png")));
+        assert!(!auth.verify("/img.png?alpaca-padding=300"));
+        let other = ParamAuth::new(b"other", Duration::from_secs(60), Unauthenticated::Refuse);
+        assert!(!other.verify(&uri));
+        assert_eq!(auth.parse_target_size("/img.png?alpaca-padding=300"), None);
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let ttl = Duration::from_secs(60);
+        let auth = ParamAuth::new_encrypted(b"secret", ttl, Unauthenticated::Refuse);
+        let params = "alpaca-padding=300&alpaca-refs=1000,2000";
+        let uri = protect(&auth, "/frame.html?v=1", params);
+        assert!(uri.starts_with("/frame.html?v=1&alpaca-token="));
+        assert!(!uri.contains("300"));
+        let opened = auth.open(&uri).unwrap();
+        assert!(opened.starts_with("/frame.html?v=1&alpaca-padding=300&alpaca-refs=1000,2000&"));
+        assert_eq!(auth.parse_target_size(&uri), Some(300));
+        let bound = get_auth_params_bound(Some(&auth), params.len());
+        assert!(uri.len() - "/frame.html?v=1&".len() <= bound);
+
+        // Tokens are bound to the object's path, and to the secret.
+        assert!(!auth.verify(&uri.replace("v=1", "v=2")));
+        let token = &uri[uri.find("alpaca-token").unwrap()..];
+        assert!(!auth.verify(&format!("/big.png?{}", token)));
+        let other = ParamAuth::new_encrypted(b"other", ttl, Unauthenticated::Refuse);
+        assert!(!other.verify(&uri));
+        assert!(!auth.verify("/img.png?alpaca-token=AAAA"));
+        assert!(!auth.verify(&uri[..uri.len() - 1]));
+        // Plaintext parameters are not authenticated.
+        assert_eq!(auth.parse_target_size("/img.png?alpaca-padding=300"), None);
+    }
+
+    #[test]
+    fn test_expiry_and_clamp() {
+        let ttl = Duration::from_secs(0);
+        for auth in &[
+            ParamAuth::new(b"secret", ttl, Unauthenticated::Clamp(500)),
+            ParamAuth::new_encrypted(b"secret", ttl, Unauthenticated::Clamp(500)),
+        ] {
+            let uri = protect(auth, "/img.png", "alpaca-padding=900");
+            assert!(!auth.verify(&uri));
+            assert_eq!(
+                auth.parse_target_size("/img.png?alpaca-padding=900"),
+                Some(500)
+            );
+            assert_eq!(
+                auth.parse_target_size("/img.png?alpaca-padding=300"),
+                Some(300)
+            );
+        }
+
+        assert_eq!(
+            get_target_size("/img.png?alpaca-padding=900", None),
+            Some(900)
+        );
+        assert_eq!(
+            open_request("GET /img.png HTTP/1.1", None),
+            Some("/img.png".to_string())
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Caches morphing decisions, so that reloading a page gives the same sizes.
+//!
+//! Sampling new sizes for every load of a page lets an attacker who observes
+//! many loads average the noise out. A page morphed from the cache is the
+//! same as when it was first morphed (the padding bytes aside), for as long
+//! as neither it nor the objects it fetches change.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use graph::PageGraph;
+
+/// A page as morphed, when it was morphed from a given version.
+struct CachedMorph {
+    /// Fingerprint of the version of the page (see `get_fingerprint`).
+    fingerprint: u64,
+    /// The morphed HTML page, before padding.
+    content: Vec<u8>,
+    /// Size to pad the HTML page to.
+    target_size: usize,
+    /// When the entry expires.
+    expires: Instant,
+    /// When the entry was last used, in uses of the cache.
+    last_used: u64,
+}
+
+/// A cache of morphed pages, keyed by their URL.
+///
+/// Entries expire after a time to live, and once the cache is full, the
+/// least recently used entry is evicted to make room for a new one.
+pub struct DecisionCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: HashMap<String, CachedMorph>,
+    uses: u64,
+}
+
+impl DecisionCache {
+    /// Construct a DecisionCache of at most `capacity` pages, each kept for
+    /// `ttl`.
+    pub fn new(ttl: Duration, capacity: usize) -> DecisionCache {
+        DecisionCache {
+            ttl,
+            capacity,
+            entries: HashMap::new(),
+            uses: 0,
+        }
+    }
+
+    /// Returns the morphed page at `url` and the size to pad it to, if it
+    /// is cached for the version of the page with `fingerprint` and has not
+    /// expired.
+    pub fn get(&mut self, url: &str, fingerprint: u64) -> Option<(&[u8], usize)> {
+        let now = Instant::now();
+        let fresh = match self.entries.get(url) {
+            Some(entry) => entry.fingerprint == fingerprint && now < entry.expires,
+            None => return None,
+        };
+        if !fresh {
+            self.entries.remove(url);
+            return None;
+        }
+
+        self.uses += 1;
+        let entry = self.entries.get_mut(url)?;
+        entry.last_used = self.uses;
+        Some((&entry.content, entry.target_size))
+    }
+
+    /// Caches the morphed page at `url` and the size to pad it to, for the
+    /// version of the page with `fingerprint`.
+    pub fn insert(&mut self, url: &str, fingerprint: u64, content: &[u8], target_size: usize) {
+        let ttl = self.ttl;
+        self.insert_with_ttl(url, fingerprint, content, target_size, ttl);
+    }
+
+    /// Caches a morphed page as `insert` does, for at most `ttl` (e.g., for
+    /// as long as the parameters of its references are valid).
+    pub(crate) fn insert_with_ttl(
+        &mut self,
+        url: &str,
+        fingerprint: u64,
+        content: &[u8],
+        target_size: usize,
+        ttl: Duration,
+    ) {
+        let ttl = ttl.min(self.ttl);
+        if self.capacity == 0 || ttl == Duration::from_secs(0) {
+            return;
+        }
+        let now = Instant::now();
+        if !self.entries.contains_key(url) && self.entries.len() >= self.capacity {
+            self.entries.retain(|_, entry| now < entry.expires);
+        }
+        if !self.entries.contains_key(url) && self.entries.len() >= self.capacity {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|&(_, entry)| entry.last_used)
+                .map(|(url, _)| url.clone());
+            if let Some(lru) = lru {
+                self.entries.remove(&lru);
+            }
+        }
+
+        self.uses += 1;
+        self.entries.insert(
+            url.to_string(),
+            CachedMorph {
+                fingerprint,
+                content: content.to_vec(),
+                target_size,
+                expires: now + ttl,
+                last_used: self.uses,
+            },
+        );
+    }
+
+    /// Removes the page at `url` from the cache, e.g. when it changes.
+    pub fn invalidate(&mut self, url: &str) {
+        self.entries.remove(url);
+    }
+
+    /// Removes every page from the cache.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns the number of pages in the cache, expired ones included.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache holds no page.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Returns a fingerprint of the version of the page of the graph: its
+/// content, and the path and size of each object it fetches.
+pub(crate) fn get_fingerprint(graph: &PageGraph) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    graph.nodes[0].object.content.hash(&mut hasher);
+    for node in &graph.nodes[1..] {
+        node.path.hash(&mut hasher);
+        node.object.size.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use objects::Object;
+
+    #[test]
+    fn test_get_and_insert() {
+        let mut cache = DecisionCache::new(Duration::from_secs(60), 2);
+        assert!(cache.get("/a.html", 1).is_none());
+        cache.insert("/a.html", 1, b"<p>a</p>", 100);
+        assert_eq!(cache.get("/a.html", 1), Some((&b"<p>a</p>"[..], 100)));
+        // A different version of the page is not served from the cache.
+        assert!(cache.get("/a.html", 2).is_none());
+        assert!(cache.is_empty());
+
+        cache.insert("/a.html", 1, b"<p>a</p>", 100);
+        cache.invalidate("/a.html");
+        assert!(cache.get("/a.html", 1).is_none());
+    }
+
+    #[test]
+    fn test_expiry_and_eviction() {
+        let mut cache = DecisionCache::new(Duration::from_secs(0), 2);
+        cache.insert("/a.html", 1, b"a", 100);
+        assert!(cache.get("/a.html", 1).is_none());
+
+        // The least recently used page is evicted.
+        let mut cache = DecisionCache::new(Duration::from_secs(60), 2);
+        cache.insert("/a.html", 1, b"a", 100);
+        cache.insert("/b.html", 1, b"b", 200);
+        assert!(cache.get("/a.html", 1).is_some());
+        cache.insert("/c.html", 1, b"c", 300);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("/b.html", 1).is_none());
+        assert!(cache.get("/a.html", 1).is_some());
+        assert!(cache.get("/c.html", 1).is_some());
+
+        cache.clear();
+        assert!(cache.is_empty());
+
+        // Entries may expire earlier than the cache's time to live.
+        cache.insert_with_ttl("/a.html", 1, b"a", 100, Duration::from_secs(0));
+        assert!(cache.is_empty());
+        cache.insert_with_ttl("/a.html", 1, b"a", 100, Duration::from_secs(30));
+        assert!(cache.get("/a.html", 1).is_some());
+    }
+
+    #[test]
+    fn test_get_fingerprint() {
+        let mut lookup = HashMap::new();
+        lookup.insert("/a.png".to_string(), vec![0u8; 100]);
+        let page = Object::from(b"<img src=a.png>", "/index.html");
+        let fingerprint = get_fingerprint(&PageGraph::build(&page, &lookup));
+        assert_eq!(
+            get_fingerprint(&PageGraph::build(&page, &lookup)),
+            fingerprint
+        );
+
+        // The page changes with its content, or with the objects it fetches.
+        let changed = Object::from(b"<img src=a.png >", "/index.html");
+        assert_ne!(
+            get_fingerprint(&PageGraph::build(&changed, &lookup)),
+            fingerprint
+        );
+        lookup.insert("/a.png".to_string(), vec![0u8; 200]);
+        assert_ne!(
+            get_fingerprint(&PageGraph::build(&page, &lookup)),
+            fingerprint
+        );
+    }
+}
@@ -1,84 +1,1228 @@
 //! Provides functions to sample objects' count and size from a
 //! probability distribution.
+//!
+//! Pages are sampled jointly (see `SamplingConfig::sample_page`): the count
+//! and sizes of their objects, and the size of their HTML, all depend on a
+//! latent scale of the page, so that big pages tend to have many big
+//! objects.
+//!
+//! Each of these is sampled from a distribution of the configuration (see
+//! `SamplingConfig::parse`): log-normal, Pareto, Weibull or gamma, or a
+//! finite mixture of these.
 use rand::Rng;
-use rand::distributions::{LogNormal, Sample};
+use rand::distributions::normal::StandardNormal;
+use std::f64::consts::{LN_2, PI, SQRT_2};
+use std::fs;
+use std::path::Path;
 
-/// Parameters for a log-normal distribution.
-struct DistParams {
-    mean: f64,
-    std_dev: f64,
+use objects::ObjectKind;
+use pad::min_padding_size;
+
+/// A probability distribution of sizes (or counts).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Distribution {
+    /// Log-normal distribution.
+    LogNormal {
+        /// Mean of the logarithm.
+        mean: f64,
+        /// Standard deviation of the logarithm.
+        std_dev: f64,
+    },
+    /// Pareto distribution.
+    Pareto {
+        /// Minimum of the distribution.
+        scale: f64,
+        /// Tail index: the smaller, the heavier the tail.
+        shape: f64,
+    },
+    /// Weibull distribution.
+    Weibull {
+        /// Scale of the distribution.
+        scale: f64,
+        /// Shape of the distribution: below 1, the tail is heavier than an
+        /// exponential's.
+        shape: f64,
+    },
+    /// Gamma distribution.
+    Gamma {
+        /// Shape of the distribution.
+        shape: f64,
+        /// Scale of the distribution.
+        scale: f64,
+    },
+    /// Finite mixture of distributions, each with its weight. The weights
+    /// sum to 1.
+    Mixture(Vec<(f64, Distribution)>),
 }
 
-// Hardcoded parameters for log-normal distributions.
-const OBJECT_COUNT_DIST: DistParams = DistParams {
+// Default distributions, which are log-normal.
+const OBJECT_COUNT_DIST: Distribution = Distribution::LogNormal {
     mean: 0.4,
     std_dev: 2.0,
 };
-const OBJECT_SIZE_DIST: DistParams = DistParams {
+const OBJECT_SIZE_DIST: Distribution = Distribution::LogNormal {
     mean: 8.8,
     std_dev: 1.0,
 };
-const HTML_SIZE_DIST: DistParams = DistParams {
+const IMG_SIZE_DIST: Distribution = Distribution::LogNormal {
+    mean: 8.9,
+    std_dev: 1.3,
+};
+const CSS_SIZE_DIST: Distribution = Distribution::LogNormal {
+    mean: 8.2,
+    std_dev: 1.2,
+};
+const JS_SIZE_DIST: Distribution = Distribution::LogNormal {
+    mean: 9.4,
+    std_dev: 1.4,
+};
+const FONT_SIZE_DIST: Distribution = Distribution::LogNormal {
+    mean: 10.1,
+    std_dev: 0.6,
+};
+const HTML_SIZE_DIST: Distribution = Distribution::LogNormal {
     mean: 8.8,
     std_dev: 1.0,
 };
 
-// Number of tries per sample. If no sampled number satisfies a specified
-// threshold after `SAMPLE_LIMIT` tries the sampling function returns Err.
-const SAMPLE_LIMIT: usize = 30;
+// Correlation of the normal scores of a page's object count, object sizes
+// and HTML size with the page's scale. Their marginal distributions are
+// those of the configuration.
+const PAGE_SCALE_CORRELATION: f64 = 0.5;
+
+// Coefficients, by decreasing degree, of P. J. Acklam's rational
+// approximation of the standard normal distribution's inverse CDF (relative
+// error below 1.15e-9), in its central region and in its tails (below
+// `ICDF_TAIL`).
+const ICDF_CENTRAL_NUM: [f64; 6] = [
+    -3.969683028665376e+01,
+    2.209460984245205e+02,
+    -2.759285104469687e+02,
+    1.38357751867269e+02,
+    -3.066479806614716e+01,
+    2.506628277459239e+00,
+];
+const ICDF_CENTRAL_DEN: [f64; 6] = [
+    -5.447609879822406e+01,
+    1.615858368580409e+02,
+    -1.556989798598866e+02,
+    6.680131188771972e+01,
+    -1.328068155288572e+01,
+    1.0,
+];
+const ICDF_TAIL_NUM: [f64; 6] = [
+    -7.784894002430293e-03,
+    -3.223964580411365e-01,
+    -2.400758277161838e+00,
+    -2.549671010466138e+00,
+    4.374664141464968e+00,
+    2.938163982698783e+00,
+];
+const ICDF_TAIL_DEN: [f64; 5] = [
+    7.784695709041462e-03,
+    3.224671290700398e-01,
+    2.445134137142996e+00,
+    3.754408661907416e+00,
+    1.0,
+];
+const ICDF_TAIL: f64 = 0.02425;
+// Coefficients, by decreasing degree, of the Chebyshev approximation of the
+// complementary error function from Numerical Recipes' `erfcc` (relative
+// error below 1.2e-7).
+const ERFC_COEFFS: [f64; 10] = [
+    0.17087277,
+    -0.82215223,
+    1.48851587,
+    -1.13520398,
+    0.27886807,
+    -0.18628806,
+    0.09678418,
+    0.37409196,
+    1.00002368,
+    -1.26551223,
+];
+
+// Coefficients of the Lanczos approximation of the gamma function, from
+// Numerical Recipes' `gammln` (relative error below 2e-10).
+const LANCZOS_COEFFS: [f64; 6] = [
+    76.18009172947146,
+    -86.50532032941677,
+    24.01409824083091,
+    -1.231739572450155,
+    0.1208650973866179e-2,
+    -0.5395239384953e-5,
+];
+// Relative precision of the series and continued fraction of the
+// incomplete gamma function.
+const GAMMA_EPSILON: f64 = 1e-15;
+// Bounds of the logarithm of the sizes searched when inverting a CDF, and
+// maximum number of steps of the search.
+const LN_SIZE_BOUND: f64 = 700.0;
+const INVERSION_STEPS: usize = 100;
+
+impl Distribution {
+    /// Returns the logarithm of the distribution's survival function,
+    /// S(x) = P(X >= x).
+    fn ln_sf(&self, x: f64) -> f64 {
+        if x <= 0.0 {
+            return 0.0;
+        }
+        match *self {
+            Distribution::LogNormal { mean, std_dev } => {
+                ln_normal_sf((x.ln() - mean) / std_dev)
+            }
+            Distribution::Pareto { scale, shape } => shape * (scale.ln() - x.ln()).min(0.0),
+            Distribution::Weibull { scale, shape } => -(x / scale).powf(shape),
+            Distribution::Gamma { shape, scale } => ln_gamma_q(shape, x / scale),
+            Distribution::Mixture(ref components) => {
+                let terms = components
+                    .iter()
+                    .map(|&(weight, ref dist)| weight.ln() + dist.ln_sf(x))
+                    .collect::<Vec<_>>();
+                ln_sum_exp(&terms)
+            }
+        }
+    }
+
+    /// Returns the x such that S(x) = e^`ln_q`, inverse of `ln_sf`.
+    fn isf_ln(&self, ln_q: f64) -> f64 {
+        let ln_q = ln_q.min(0.0);
+        match *self {
+            Distribution::LogNormal { mean, std_dev } => {
+                (mean + std_dev * normal_isf_ln(ln_q)).exp()
+            }
+            Distribution::Pareto { scale, shape } => scale * (-ln_q / shape).exp(),
+            Distribution::Weibull { scale, shape } => scale * (-ln_q).powf(1.0 / shape),
+            Distribution::Gamma { .. } | Distribution::Mixture(_) => {
+                invert_ln_sf(|x| self.ln_sf(x), ln_q)
+            }
+        }
+    }
+
+    /// Returns the normal score of `x`: the standard normal quantile of the
+    /// distribution's CDF at `x`.
+    fn score(&self, x: f64) -> f64 {
+        match *self {
+            Distribution::LogNormal { mean, std_dev } => (x.ln() - mean) / std_dev,
+            _ => normal_isf_ln(self.ln_sf(x)),
+        }
+    }
+
+    /// Returns the x whose normal score is `z`, inverse of `score`.
+    fn inverse_score(&self, z: f64) -> f64 {
+        match *self {
+            Distribution::LogNormal { mean, std_dev } => (mean + std_dev * z).exp(),
+            _ => self.isf_ln(ln_normal_sf(z)),
+        }
+    }
+
+    /// Returns whether the parameters are those of a distribution: finite,
+    /// and positive but for the log-normal's mean.
+    fn is_valid(&self) -> bool {
+        let positive = |p: f64| p.is_finite() && p > 0.0;
+        match *self {
+            Distribution::LogNormal { mean, std_dev } => mean.is_finite() && positive(std_dev),
+            Distribution::Pareto { scale, shape }
+            | Distribution::Weibull { scale, shape }
+            | Distribution::Gamma { shape, scale } => positive(scale) && positive(shape),
+            Distribution::Mixture(ref components) => {
+                !components.is_empty()
+                    && components
+                        .iter()
+                        .all(|&(weight, ref dist)| positive(weight) && dist.is_valid())
+            }
+        }
+    }
+}
+
+/// A budget of bytes for loading a morphed page: its HTML, the objects it
+/// fetches and its padding objects.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget {
+    /// At most this many bytes.
+    Bytes(usize),
+    /// At most this many times the bytes of loading the original page.
+    Ratio(f64),
+}
+
+impl Budget {
+    /// Returns the number of bytes of the budget, for a page whose original
+    /// load is `original_size` bytes.
+    pub fn bytes(self, original_size: usize) -> usize {
+        match self {
+            Budget::Bytes(bytes) => bytes,
+            Budget::Ratio(ratio) => (original_size as f64 * ratio) as usize,
+        }
+    }
+}
+
+/// The distributions pages are sampled from, and the bounds of the sampled
+/// sizes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SamplingConfig {
+    /// Distribution of the number of objects of a page.
+    pub object_count: Distribution,
+    /// Distribution of the size of HTML pages.
+    pub html_size: Distribution,
+    /// Distribution of the sizes of images.
+    pub img_size: Distribution,
+    /// Distribution of the sizes of stylesheets.
+    pub css_size: Distribution,
+    /// Distribution of the sizes of scripts.
+    pub js_size: Distribution,
+    /// Distribution of the sizes of fonts.
+    pub font_size: Distribution,
+    /// Distribution of the sizes of objects of any other kind.
+    pub object_size: Distribution,
+    /// Maximum size of HTML pages, or None.
+    pub max_html_size: Option<usize>,
+    /// Maximum size of the other objects, padding objects included, or None.
+    pub max_object_size: Option<usize>,
+    /// Budget of bytes for loading a morphed page, or None.
+    pub budget: Option<Budget>,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> SamplingConfig {
+        SamplingConfig {
+            object_count: OBJECT_COUNT_DIST,
+            html_size: HTML_SIZE_DIST,
+            img_size: IMG_SIZE_DIST,
+            css_size: CSS_SIZE_DIST,
+            js_size: JS_SIZE_DIST,
+            font_size: FONT_SIZE_DIST,
+            object_size: OBJECT_SIZE_DIST,
+            max_html_size: None,
+            max_object_size: None,
+            budget: None,
+        }
+    }
+}
+
+impl SamplingConfig {
+    /// Reads a configuration from the file at `path` (see `parse`).
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SamplingConfig, ()> {
+        let config = fs::read_to_string(path).map_err(|_| ())?;
+        SamplingConfig::parse(&config)
+    }
+
+    /// Parses a configuration, made of lines `key = distribution`, where the
+    /// key is one of `count`, `html`, `img`, `css`, `js`, `font` and
+    /// `object`, and the distribution one of
+    ///
+    /// ```text
+    /// lognormal(mean, std_dev)
+    /// pareto(scale, shape)
+    /// weibull(scale, shape)
+    /// gamma(shape, scale)
+    /// mixture(weight distribution, ...)
+    /// ```
+    ///
+    /// e.g., `img = mixture(0.8 lognormal(8.0, 1.0), 0.2 pareto(20000, 1.5))`.
+    /// The weights of a mixture are normalized.
+    ///
+    /// Sizes are bounded by the keys `max_html` and `max_object`, in bytes,
+    /// and `budget`, in bytes or as a ratio of the original page's (e.g.,
+    /// `budget = 2.5x`).
+    ///
+    /// Keys which are not given keep their default, and `#` starts a
+    /// comment.
+    ///
+    /// # Returns
+    /// The configuration, or Err(()) if it is malformed.
+    pub fn parse(config: &str) -> Result<SamplingConfig, ()> {
+        let mut sampling = SamplingConfig::default();
+        for line in config.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let eq = line.find('=').ok_or(())?;
+            let (key, value) = (line[..eq].trim(), line[eq + 1..].trim());
+            match key {
+                "max_html" => {
+                    sampling.max_html_size = Some(value.parse().map_err(|_| ())?);
+                    continue;
+                }
+                "max_object" => {
+                    sampling.max_object_size = Some(value.parse().map_err(|_| ())?);
+                    continue;
+                }
+                "budget" => {
+                    sampling.budget = Some(parse_budget(value)?);
+                    continue;
+                }
+                _ => {}
+            }
+            let dist = parse_distribution(value)?;
+            let field = match key {
+                "count" => &mut sampling.object_count,
+                "html" => &mut sampling.html_size,
+                "img" => &mut sampling.img_size,
+                "css" => &mut sampling.css_size,
+                "js" => &mut sampling.js_size,
+                "font" => &mut sampling.font_size,
+                "object" => &mut sampling.object_size,
+                _ => return Err(()),
+            };
+            *field = dist;
+        }
+        Ok(sampling)
+    }
+
+    /// Samples a new page, with at least `min_count` objects.
+    pub fn sample_page<R: Rng>(&self, rng: &mut R, min_count: usize) -> PageSample<'_> {
+        let StandardNormal(scale) = rng.gen();
+        let object_count = sample_scaled(rng, &self.object_count, scale, min_count, usize::MAX);
+        PageSample {
+            config: self,
+            scale,
+            object_count,
+        }
+    }
+
+    /// Samples a new object count.
+    ///
+    /// Samples a new object count from the distribution `object_count`.
+    pub fn sample_object_count<R: Rng>(&self, rng: &mut R, ge: usize) -> usize {
+        sample(rng, &self.object_count, ge, usize::MAX)
+    }
+
+    /// Samples the target size of an HTML page of size `size`, of at most
+    /// `le` bytes (and `max_html_size`) unless it must be larger than that
+    /// to fit the page and the syntax of its padding.
+    ///
+    /// Samples the size of an HTML page from the distribution `html_size`.
+    pub fn sample_html_size<R: Rng>(&self, rng: &mut R, size: usize, le: usize) -> usize {
+        let ge = size.saturating_add(min_padding_size(&ObjectKind::HTML));
+        sample(rng, &self.html_size, ge, self.get_max_html_size(le))
+    }
+
+    /// Samples the target size of an object of kind `kind` and size `size`.
+    ///
+    /// Samples the size of an object from the distribution of its kind (see
+    /// `get_size_dist`), of at most `max_object_size` unless the object is
+    /// larger. The target size leaves room for the syntax of the object's
+    /// padding (see `min_padding_size`), so that it can be padded to it.
+    pub fn sample_object_size<R: Rng>(&self, rng: &mut R, kind: ObjectKind, size: usize) -> usize {
+        let ge = size.saturating_add(min_padding_size(&kind));
+        sample(rng, self.get_size_dist(kind), ge, self.get_max_object_size())
+    }
+
+    /// Samples the sizes of n objects of kind `kind`.
+    ///
+    /// Samples the sizes of n objects from the distribution of their kind
+    /// (see `get_size_dist`).
+    pub fn sample_object_sizes<R: Rng>(
+        &self,
+        rng: &mut R,
+        kind: ObjectKind,
+        n: usize,
+    ) -> Vec<usize> {
+        let ge = 1;
+        let dist = self.get_size_dist(kind);
+        let le = self.get_max_object_size();
+        (0..n).map(|_| sample(rng, dist, ge, le)).collect()
+    }
+
+    /// Returns the maximum size of an HTML page, given its maximum size `le`
+    /// otherwise.
+    pub(crate) fn get_max_html_size(&self, le: usize) -> usize {
+        self.max_html_size.map_or(le, |max| max.min(le))
+    }
+
+    /// Returns the maximum size of an object other than an HTML page.
+    fn get_max_object_size(&self) -> usize {
+        self.max_object_size.unwrap_or(usize::MAX)
+    }
+
+    /// Returns the distribution of the sizes of objects of kind `kind`:
+    /// `img_size`, `css_size`, `js_size` or `font_size`, and `object_size`
+    /// for any other kind.
+    fn get_size_dist(&self, kind: ObjectKind) -> &Distribution {
+        match kind {
+            ObjectKind::IMG | ObjectKind::SVG => &self.img_size,
+            ObjectKind::CSS => &self.css_size,
+            ObjectKind::JS => &self.js_size,
+            ObjectKind::Font => &self.font_size,
+            _ => &self.object_size,
+        }
+    }
+}
+
+/// A page sampled from the joint distribution of pages, from which its
+/// characteristics are sampled.
+pub struct PageSample<'a> {
+    /// The distributions the page is sampled from.
+    config: &'a SamplingConfig,
+    /// The page's scale, from a standard normal distribution.
+    scale: f64,
+    /// The number of objects of the page.
+    pub object_count: usize,
+}
+
+impl<'a> PageSample<'a> {
+    /// Samples the target size of an object of kind `kind` and size `size`
+    /// of the page (see `SamplingConfig::sample_object_size`).
+    pub fn sample_object_size<R: Rng>(
+        &self,
+        rng: &mut R,
+        kind: ObjectKind,
+        size: usize,
+    ) -> usize {
+        let ge = size.saturating_add(min_padding_size(&kind));
+        let dist = self.config.get_size_dist(kind);
+        let le = self.config.get_max_object_size();
+        sample_scaled(rng, dist, self.scale, ge, le)
+    }
 
-/// Samples an `usize` number according to the log-normal distribution
-/// with the specified parameters.
+    /// Samples the target size of the HTML of the page, of size `size`, of
+    /// at most `le` bytes (see `SamplingConfig::sample_html_size`).
+    pub fn sample_html_size<R: Rng>(&self, rng: &mut R, size: usize, le: usize) -> usize {
+        let ge = size.saturating_add(min_padding_size(&ObjectKind::HTML));
+        let le = self.config.get_max_html_size(le);
+        sample_scaled(rng, &self.config.html_size, self.scale, ge, le)
+    }
+}
+
+/// Samples an `usize` number according to the distribution `dist`,
+/// conditioned on being between `ge` and `le`.
 ///
 /// # Arguments
 ///
 /// `rng` - Random number generator.
-/// `params` - Parameters (mean and standard deviation) for the log-normal
-///            distribution.
+/// `dist` - Distribution of the number.
 /// `ge` - Threshold: the sampled number should be greater than or equal to
 ///        `ge`.
+/// `le` - Bound: the sampled number should be less than or equal to `le`,
+///        unless `ge` is larger, in which case it is `ge`.
+fn sample<R: Rng>(rng: &mut R, dist: &Distribution, ge: usize, le: usize) -> usize {
+    sample_with_score(rng, dist, 0.0, 1.0, ge, le)
+}
+
+/// Samples an `usize` number according to the distribution `dist`, given
+/// the scale of the page it is part of.
+fn sample_scaled<R: Rng>(
+    rng: &mut R,
+    dist: &Distribution,
+    scale: f64,
+    ge: usize,
+    le: usize,
+) -> usize {
+    let mean = PAGE_SCALE_CORRELATION * scale;
+    let std_dev = (1.0 - PAGE_SCALE_CORRELATION.powi(2)).sqrt();
+    sample_with_score(rng, dist, mean, std_dev, ge, le)
+}
+
+/// Samples an `usize` number according to the distribution `dist`,
+/// conditioned on being between `ge` and `le`, when its normal score (see
+/// `Distribution::score`) is normal with mean `mean` and standard
+/// deviation `std_dev`. With a standard normal score, the number follows
+/// `dist` itself.
+///
+/// The score is sampled by inverting the normal CDF between the scores of
+/// `ge` and `le`, which takes constant time for any bounds.
 ///
 /// # Returns
-/// The sampled number. The function tries `SAMPLE_LIMIT` times to sample
-/// a number satisfying the threshold. If no number satisfies it, it returns
-/// Err(()).
-fn sample<R: Rng>(rng: &mut R, params: &DistParams, ge: usize) -> Result<usize, ()> {
-    let mut dist = LogNormal::new(params.mean, params.std_dev);
+/// The sampled number, the integer part of a variable, which is between
+/// `ge` and `le` exactly when the variable is between `ge` and `le + 1`.
+fn sample_with_score<R: Rng>(
+    rng: &mut R,
+    dist: &Distribution,
+    mean: f64,
+    std_dev: f64,
+    ge: usize,
+    le: usize,
+) -> usize {
+    if ge >= le {
+        return ge;
+    }
+    // The standardized score is sampled from the standard normal
+    // distribution between `lower` and `upper`, through the survival
+    // function Q, whose logarithm keeps far tails precise: ln Q(z) is
+    // uniformly distributed between ln Q(lower) and ln Q(upper) once
+    // exponentiated.
+    let lower = (dist.score(ge as f64) - mean) / std_dev;
+    let ln_q_lower = ln_normal_sf(lower);
+    let ln_q_upper = if le == usize::MAX {
+        f64::NEG_INFINITY
+    } else {
+        ln_normal_sf((dist.score(le as f64 + 1.0) - mean) / std_dev)
+    };
+    let u = rng.gen::<f64>();
+    let ln_q = ln_q_lower + (u * (ln_q_upper - ln_q_lower).exp_m1()).ln_1p();
+    let z = normal_isf_ln(ln_q);
 
-    for _ in 0..SAMPLE_LIMIT {
-        let x = dist.sample(rng) as usize;
-        if x >= ge {
-            return Ok(x);
+    let x = dist.inverse_score(mean + std_dev * z);
+    // Rounding errors may take the variable just beyond the bounds.
+    (x as usize).max(ge).min(le)
+}
+
+/// Returns the logarithm of the standard normal distribution's survival
+/// function, Q(x) = P(Z >= x).
+fn ln_normal_sf(x: f64) -> f64 {
+    if x < 0.0 {
+        return (-ln_normal_sf(-x).exp()).ln_1p();
+    }
+    // Q(x) = erfc(x / sqrt(2)) / 2.
+    let z = x / SQRT_2;
+    let t = 1.0 / (1.0 + 0.5 * z);
+    t.ln() - z * z + horner(&ERFC_COEFFS, t) - LN_2
+}
+
+/// Returns the x such that Q(x) = e^`ln_q`, inverse of `ln_normal_sf`.
+fn normal_isf_ln(ln_q: f64) -> f64 {
+    if ln_q >= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if ln_q == f64::NEG_INFINITY {
+        return f64::INFINITY;
+    }
+    let q = ln_q.exp();
+    let mut x = if q > 1.0 - ICDF_TAIL {
+        let r = (-2.0 * (-ln_q.exp_m1()).ln()).sqrt();
+        horner(&ICDF_TAIL_NUM, r) / horner(&ICDF_TAIL_DEN, r)
+    } else if q >= ICDF_TAIL {
+        let r = q - 0.5;
+        -r * horner(&ICDF_CENTRAL_NUM, r * r) / horner(&ICDF_CENTRAL_DEN, r * r)
+    } else {
+        let r = (-2.0 * ln_q).sqrt();
+        -horner(&ICDF_TAIL_NUM, r) / horner(&ICDF_TAIL_DEN, r)
+    };
+    // The approximation is refined with Newton's method on ln Q, whose
+    // derivative is -pdf(x) / Q(x), which keeps it precise far in the tail.
+    // Beyond where the logarithms cancel out precisely, it is left as is.
+    for _ in 0..2 {
+        let ln_pdf = -0.5 * x * x - 0.5 * (2.0 * PI).ln();
+        let ln_sf = ln_normal_sf(x);
+        let step = (ln_sf - ln_q) / (ln_pdf - ln_sf).exp();
+        if step.is_finite() {
+            x += step;
         }
     }
+    x
+}
 
-    Err(())
+/// Evaluates the polynomial with coefficients `coeffs`, by decreasing
+/// degree, at `x`.
+fn horner(coeffs: &[f64], x: f64) -> f64 {
+    coeffs.iter().fold(0.0, |acc, &c| acc * x + c)
 }
 
-/// Samples a new object count.
-///
-/// Samples a new object count from a log-normal distribution
-/// specified by the distribution parameters `OBJECT_COUNT_DIST`.
-pub fn sample_object_count<R: Rng>(rng: &mut R, ge: usize) -> Result<usize, ()> {
-    sample(rng, &OBJECT_COUNT_DIST, ge)
+/// Returns the x such that ln S(x) = `ln_q`, given the logarithm `ln_sf` of
+/// a survival function S, by regula falsi (Illinois variant) on the
+/// logarithm of x.
+fn invert_ln_sf<F: Fn(f64) -> f64>(ln_sf: F, ln_q: f64) -> f64 {
+    if ln_q >= 0.0 {
+        return 0.0;
+    }
+    if ln_q == f64::NEG_INFINITY {
+        return f64::INFINITY;
+    }
+    // f is decreasing, and changes sign between lo and hi.
+    let f = |t: f64| ln_sf(t.exp()) - ln_q;
+    let (mut lo, mut hi) = (-1.0f64, 1.0f64);
+    while f(lo) < 0.0 && lo > -LN_SIZE_BOUND {
+        lo *= 2.0;
+    }
+    while f(hi) > 0.0 && hi < LN_SIZE_BOUND {
+        hi *= 2.0;
+    }
+
+    let (mut f_lo, mut f_hi) = (f(lo), f(hi));
+    let mut side = 0;
+    for _ in 0..INVERSION_STEPS {
+        if f_lo == f_hi || hi - lo <= f64::EPSILON * hi.abs().max(1.0) {
+            break;
+        }
+        let t = hi - f_hi * (hi - lo) / (f_hi - f_lo);
+        let f_t = f(t);
+        if f_t == 0.0 {
+            return t.exp();
+        }
+        // The end which stays in place twice in a row has its value halved,
+        // so that the interval keeps shrinking on both sides.
+        if f_t > 0.0 {
+            lo = t;
+            f_lo = f_t;
+            if side == 1 {
+                f_hi /= 2.0;
+            }
+            side = 1;
+        } else {
+            hi = t;
+            f_hi = f_t;
+            if side == -1 {
+                f_lo /= 2.0;
+            }
+            side = -1;
+        }
+    }
+    (0.5 * (lo + hi)).exp()
 }
 
-/// Samples the size of an HTML page.
-///
-/// Samples the size of an HTML page from a log-normal distribution
-/// specified by the distribution parameters `HTML_SIZE_DIST`.
-pub fn sample_html_size<R: Rng>(rng: &mut R, ge: usize) -> Result<usize, ()> {
-    sample(rng, &HTML_SIZE_DIST, ge)
+/// Returns the logarithm of the regularized upper incomplete gamma function,
+/// Q(a, x), which is the survival function of the gamma distribution of
+/// shape `a` and scale 1.
+fn ln_gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    // ln(e^-x x^a / Γ(a)).
+    let ln_prefix = -x + a * x.ln() - ln_gamma(a);
+    if x < a + 1.0 {
+        // The series of P(a, x) = 1 - Q(a, x) converges quickly.
+        let (mut term, mut sum) = (1.0 / a, 1.0 / a);
+        let mut n = a;
+        while term.abs() > sum.abs() * GAMMA_EPSILON {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+        }
+        return (-(ln_prefix + sum.ln()).exp()).ln_1p();
+    }
+    // Otherwise, so does the continued fraction of Q(a, x), evaluated with
+    // Lentz's method.
+    let tiny = f64::MIN_POSITIVE / GAMMA_EPSILON;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    let mut i = 1.0;
+    loop {
+        let an = -i * (i - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() <= GAMMA_EPSILON {
+            break;
+        }
+        i += 1.0;
+    }
+    ln_prefix + h.ln()
 }
 
-/// Samples a new object count.
-///
-/// Samples the sizes of n objects from a log-normal distribution
-/// specified by the distribution parameters `OBJECT_SIZE_DIST`.
-pub fn sample_object_sizes<R: Rng>(rng: &mut R, n: usize) -> Result<Vec<usize>, ()> {
-    let ge = 1;
-    (0..n)
-        .into_iter()
-        .map(|_| sample(rng, &OBJECT_SIZE_DIST, ge))
-        .collect()
+/// Returns the logarithm of the gamma function at `x` > 0.
+fn ln_gamma(x: f64) -> f64 {
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = LANCZOS_COEFFS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |acc, (i, &c)| acc + c / (x + 1.0 + i as f64));
+    -tmp + ((2.0 * PI).sqrt() * series / x).ln()
+}
+
+/// Returns ln(e^t1 + e^t2 + ...) for the terms `terms`, without overflow.
+fn ln_sum_exp(terms: &[f64]) -> f64 {
+    let max = terms.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + terms.iter().map(|t| (t - max).exp()).sum::<f64>().ln()
+}
+
+/// Parses a budget of the configuration (see `SamplingConfig::parse`): a
+/// number of bytes, or a ratio followed by `x`.
+fn parse_budget(budget: &str) -> Result<Budget, ()> {
+    if let Some(ratio) = budget.strip_suffix('x') {
+        let ratio = ratio.trim().parse::<f64>().map_err(|_| ())?;
+        if !ratio.is_finite() || ratio < 1.0 {
+            return Err(());
+        }
+        return Ok(Budget::Ratio(ratio));
+    }
+    Ok(Budget::Bytes(budget.parse().map_err(|_| ())?))
+}
+
+/// Parses a distribution of the configuration (see `SamplingConfig::parse`).
+fn parse_distribution(dist: &str) -> Result<Distribution, ()> {
+    let dist = dist.trim();
+    let open = dist.find('(').ok_or(())?;
+    if !dist.ends_with(')') {
+        return Err(());
+    }
+    let args = split_args(&dist[open + 1..dist.len() - 1])?;
+
+    let dist = match dist[..open].trim() {
+        "mixture" => {
+            let mut components = args
+                .iter()
+                .map(|arg| {
+                    let arg = arg.trim();
+                    let space = arg.find(char::is_whitespace).ok_or(())?;
+                    let weight = arg[..space].parse::<f64>().map_err(|_| ())?;
+                    Ok((weight, parse_distribution(&arg[space..])?))
+                })
+                .collect::<Result<Vec<_>, ()>>()?;
+            let total = components.iter().map(|c| c.0).sum::<f64>();
+            for component in &mut components {
+                component.0 /= total;
+            }
+            Distribution::Mixture(components)
+        }
+        family => {
+            let params = args
+                .iter()
+                .map(|arg| arg.trim().parse::<f64>().map_err(|_| ()))
+                .collect::<Result<Vec<_>, ()>>()?;
+            if params.len() != 2 {
+                return Err(());
+            }
+            let (p0, p1) = (params[0], params[1]);
+            match family {
+                "lognormal" => Distribution::LogNormal {
+                    mean: p0,
+                    std_dev: p1,
+                },
+                "pareto" => Distribution::Pareto {
+                    scale: p0,
+                    shape: p1,
+                },
+                "weibull" => Distribution::Weibull {
+                    scale: p0,
+                    shape: p1,
+                },
+                "gamma" => Distribution::Gamma {
+                    shape: p0,
+                    scale: p1,
+                },
+                _ => return Err(()),
+            }
+        }
+    };
+
+    if dist.is_valid() {
+        Ok(dist)
+    } else {
+        Err(())
+    }
+}
+
+/// Splits the arguments of a distribution at the commas which are not
+/// nested in parentheses.
+fn split_args(args: &str) -> Result<Vec<&str>, ()> {
+    let mut split = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or(())?,
+            ',' if depth == 0 => {
+                split.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(());
+    }
+    split.push(&args[start..]);
+    Ok(split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use pad::can_pad;
+
+    fn median(mut sizes: Vec<usize>) -> usize {
+        sizes.sort_unstable();
+        sizes[sizes.len() / 2]
+    }
+
+    /// Returns the median of a distribution, where S(x) = 1/2.
+    fn dist_median(dist: &Distribution) -> f64 {
+        dist.isf_ln(-LN_2)
+    }
+
+    /// Returns a distribution of each family.
+    fn families() -> Vec<Distribution> {
+        vec![
+            Distribution::LogNormal {
+                mean: 8.0,
+                std_dev: 0.5,
+            },
+            Distribution::Pareto {
+                scale: 2000.0,
+                shape: 6.0,
+            },
+            Distribution::Weibull {
+                scale: 5000.0,
+                shape: 1.5,
+            },
+            Distribution::Gamma {
+                shape: 3.0,
+                scale: 2000.0,
+            },
+            Distribution::Mixture(vec![
+                (0.3, IMG_SIZE_DIST),
+                (
+                    0.7,
+                    Distribution::Gamma {
+                        shape: 0.5,
+                        scale: 8000.0,
+                    },
+                ),
+            ]),
+        ]
+    }
+
+    /// Returns the mean and variance of a distribution.
+    fn moments(dist: &Distribution) -> (f64, f64) {
+        let gamma = |x: f64| ln_gamma(x).exp();
+        match *dist {
+            Distribution::LogNormal { mean, std_dev } => {
+                let var = std_dev * std_dev;
+                (
+                    (mean + var / 2.0).exp(),
+                    var.exp_m1() * (2.0 * mean + var).exp(),
+                )
+            }
+            Distribution::Pareto { scale, shape } => (
+                shape * scale / (shape - 1.0),
+                scale * scale * shape / ((shape - 1.0).powi(2) * (shape - 2.0)),
+            ),
+            Distribution::Weibull { scale, shape } => {
+                let g1 = gamma(1.0 + 1.0 / shape);
+                let g2 = gamma(1.0 + 2.0 / shape);
+                (scale * g1, scale * scale * (g2 - g1 * g1))
+            }
+            Distribution::Gamma { shape, scale } => (shape * scale, shape * scale * scale),
+            Distribution::Mixture(ref components) => {
+                let mean = components
+                    .iter()
+                    .map(|&(w, ref d)| w * moments(d).0)
+                    .sum::<f64>();
+                let square = components
+                    .iter()
+                    .map(|&(w, ref d)| w * (moments(d).1 + moments(d).0.powi(2)))
+                    .sum::<f64>();
+                (mean, square - mean * mean)
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_moments() {
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let n = 50000;
+        for dist in families() {
+            // Sizes are integer parts, about half a unit below the variable.
+            let sizes = (0..n)
+                .map(|_| sample(&mut rng, &dist, 0, usize::MAX) as f64 + 0.5)
+                .collect::<Vec<_>>();
+            let mean = sizes.iter().sum::<f64>() / n as f64;
+            let var = sizes.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n as f64;
+
+            let (expected_mean, expected_var) = moments(&dist);
+            assert!((mean / expected_mean - 1.0).abs() < 0.02, "{:?}", dist);
+            assert!((var / expected_var - 1.0).abs() < 0.1, "{:?}", dist);
+        }
+    }
+
+    #[test]
+    fn test_sample_object_sizes() {
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let config = SamplingConfig::default();
+        let n = 2000;
+        let mut medians = Vec::new();
+        for &kind in &[
+            ObjectKind::CSS,
+            ObjectKind::IMG,
+            ObjectKind::JS,
+            ObjectKind::Font,
+        ] {
+            let sizes = config.sample_object_sizes(&mut rng, kind, n);
+            assert_eq!(sizes.len(), n);
+            let expected = dist_median(config.get_size_dist(kind));
+            let median = median(sizes) as f64;
+            assert!((median / expected - 1.0).abs() < 0.15);
+            medians.push(median);
+        }
+        // Stylesheets are the smallest, and fonts the largest.
+        assert!(medians.windows(2).all(|m| m[0] < m[1]));
+    }
+
+    /// Returns the correlation coefficient of two samples.
+    fn correlation(x: &[f64], y: &[f64]) -> f64 {
+        let n = x.len() as f64;
+        let mean = |v: &[f64]| v.iter().sum::<f64>() / n;
+        let (mx, my) = (mean(x), mean(y));
+        let cov = x
+            .iter()
+            .zip(y)
+            .map(|(a, b)| (a - mx) * (b - my))
+            .sum::<f64>();
+        let var = |v: &[f64], m: f64| v.iter().map(|a| (a - m).powi(2)).sum::<f64>();
+        cov / (var(x, mx) * var(y, my)).sqrt()
+    }
+
+    #[test]
+    fn test_sample_page() {
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let config = SamplingConfig::default();
+        let n = 5000;
+        let (mut counts, mut objects, mut htmls) = (Vec::new(), Vec::new(), Vec::new());
+        for _ in 0..n {
+            let page = config.sample_page(&mut rng, 0);
+            let size = page.sample_object_size(&mut rng, ObjectKind::IMG, 0);
+            let html_size = page.sample_html_size(&mut rng, 0, usize::MAX);
+            counts.push((page.object_count as f64 + 1.0).ln());
+            objects.push((size as f64 + 1.0).ln());
+            htmls.push((html_size as f64 + 1.0).ln());
+        }
+
+        // Characteristics of a page correlate with one another, through the
+        // page's scale: the correlation of their logarithms is about the
+        // square of PAGE_SCALE_CORRELATION.
+        let expected = PAGE_SCALE_CORRELATION.powi(2);
+        assert!((correlation(&objects, &htmls) - expected).abs() < 0.05);
+        assert!(correlation(&counts, &objects) > 0.1);
+        // The sizes of objects are still distributed as on their own.
+        let sizes = objects
+            .iter()
+            .map(|s| s.exp() as usize - 1)
+            .collect::<Vec<_>>();
+        let expected = dist_median(&IMG_SIZE_DIST);
+        assert!((median(sizes) as f64 / expected - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_sample_object_size() {
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let config = SamplingConfig::default();
+        for _ in 0..100 {
+            assert!(config.sample_object_size(&mut rng, ObjectKind::Font, 20000) >= 20000);
+        }
+        // Sizes are at most the maximum, unless objects are larger.
+        let config = SamplingConfig {
+            max_object_size: Some(10000),
+            ..SamplingConfig::default()
+        };
+        let sizes = config.sample_object_sizes(&mut rng, ObjectKind::JS, 1000);
+        assert!(sizes.iter().all(|s| (1..=10000).contains(s)));
+        assert_eq!(config.sample_object_size(&mut rng, ObjectKind::JS, 20000), 20004);
+        let page = config.sample_page(&mut rng, 0);
+        assert!(page.sample_object_size(&mut rng, ObjectKind::IMG, 0) <= 10000);
+        assert_eq!(page.sample_html_size(&mut rng, 500, 500), 507);
+        assert!(page.sample_html_size(&mut rng, 500, 600) <= 600);
+
+        // Thresholds far in the tail do not make sampling fail.
+        for &ge in &[1 << 30, 1 << 40, usize::MAX / 2] {
+            assert!(config.sample_object_size(&mut rng, ObjectKind::CSS, ge) >= ge);
+            for dist in families() {
+                assert!(sample(&mut rng, &dist, ge, usize::MAX) >= ge);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_sizes_paddable() {
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let configs = [
+            SamplingConfig::default(),
+            SamplingConfig {
+                max_object_size: Some(100),
+                ..SamplingConfig::default()
+            },
+        ];
+        let kinds = [
+            ObjectKind::HTML,
+            ObjectKind::CSS,
+            ObjectKind::JS,
+            ObjectKind::IMG,
+            ObjectKind::MP4,
+            ObjectKind::WebM,
+            ObjectKind::JSON,
+            ObjectKind::Unknown,
+        ];
+        // Every sampled target size is one the object can be padded to.
+        for config in &configs {
+            for _ in 0..200 {
+                let page = config.sample_page(&mut rng, 0);
+                for &kind in &kinds {
+                    let size = rng.gen_range(0, 200);
+                    let target_size = config.sample_object_size(&mut rng, kind, size);
+                    assert!(can_pad(&kind, size, target_size));
+                    let target_size = page.sample_object_size(&mut rng, kind, size);
+                    assert!(can_pad(&kind, size, target_size));
+                }
+                // Even when the page does not fit its maximum size.
+                let size = rng.gen_range(0, 200);
+                let target_size = config.sample_html_size(&mut rng, size, size);
+                assert!(can_pad(&ObjectKind::HTML, size, target_size));
+                let target_size = page.sample_html_size(&mut rng, size, size + 3);
+                assert!(can_pad(&ObjectKind::HTML, size, target_size));
+            }
+        }
+    }
+
+    #[test]
+    fn test_ln_normal_sf() {
+        let expected = [
+            (-1.96, 0.9750021048517795),
+            (0.0, 0.5),
+            (1.96, 0.024997895148220435),
+            (5.0, 2.866515718791939e-7),
+            (10.0, 7.619853024160527e-24),
+            (30.0, 4.906713927148187e-198),
+        ];
+        for &(x, q) in &expected {
+            assert!((ln_normal_sf(x) - f64::ln(q)).abs() < 1e-6);
+        }
+        // The inverse is precise across the central region and the tails.
+        for &x in &[-4.0, -2.0, -0.5, 0.0, 0.5, 2.0, 4.0, 10.0, 30.0] {
+            assert!((normal_isf_ln(ln_normal_sf(x)) - x).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_ln_gamma_q() {
+        // With shape 1 or 2, the gamma distribution's survival function is
+        // e^-x or e^-x (1 + x).
+        for &x in &[0.1, 1.0, 2.5, 10.0, 100.0] {
+            assert!((ln_gamma_q(1.0, x) + x).abs() < 1e-8);
+            assert!((ln_gamma_q(2.0, x) - (x.ln_1p() - x)).abs() < 1e-8);
+        }
+        assert!((ln_gamma(0.5) - PI.sqrt().ln()).abs() < 1e-9);
+        // Every family inverts its survival function.
+        for dist in families() {
+            for &x in &[3000.0, 20000.0, 1e5] {
+                assert!((dist.isf_ln(dist.ln_sf(x)) / x - 1.0).abs() < 1e-6);
+            }
+        }
+    }
+
+    /// Returns the Kolmogorov-Smirnov statistic of the sample `xs` against
+    /// the CDF `cdf`.
+    fn ks_statistic<F: Fn(f64) -> f64>(mut xs: Vec<f64>, cdf: F) -> f64 {
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let n = xs.len() as f64;
+        xs.iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                let f = cdf(x);
+                (f - i as f64 / n).max((i + 1) as f64 / n - f)
+            })
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn test_sample_conditional_distribution() {
+        let mut rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let n = 50000;
+        let dist = IMG_SIZE_DIST;
+        // No threshold, the median, a threshold rejection sampling would
+        // practically never reach (about 10 standard deviations away), and
+        // bounds around the median.
+        let median = dist_median(&dist) as usize;
+        let bounds = [
+            (0, usize::MAX),
+            (median, usize::MAX),
+            (1 << 26, usize::MAX),
+            (median / 2, median * 2),
+        ];
+        for &(ge, le) in &bounds {
+            // Sizes are integer parts: their scores are those of the
+            // variable, up to the rounding of a unit.
+            let lower = dist.score(ge as f64);
+            let upper = dist.score(le as f64 + 1.0);
+            let zs = (0..n)
+                .map(|_| sample(&mut rng, &dist, ge, le))
+                .map(|x| dist.score(x as f64 + 0.5))
+                .collect::<Vec<_>>();
+            assert!(zs.iter().all(|&z| z >= lower && z <= upper));
+
+            // The conditional CDF of Z given lower <= Z <= upper.
+            let (ln_q_lower, ln_q_upper) = (ln_normal_sf(lower), ln_normal_sf(upper));
+            let cdf = |z: f64| {
+                (ln_normal_sf(z) - ln_q_lower).exp_m1() / (ln_q_upper - ln_q_lower).exp_m1()
+            };
+            // Critical value of the test at significance level 0.01.
+            assert!(ks_statistic(zs, cdf) < 1.63 / (n as f64).sqrt());
+        }
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = SamplingConfig::parse(
+            "# Sizes of images: icons, and some photos.\n\
+             img = mixture(3 lognormal(7, 1), 1 pareto(20000, 1.5))\n\
+             \n\
+             font = gamma(4, 10000)  # Full fonts.\n\
+             count=weibull(10,0.8)\n\
+             max_object = 2000000\n\
+             budget = 2.5x",
+        )
+        .unwrap();
+        assert_eq!(
+            config.img_size,
+            Distribution::Mixture(vec![
+                (
+                    0.75,
+                    Distribution::LogNormal {
+                        mean: 7.0,
+                        std_dev: 1.0,
+                    },
+                ),
+                (
+                    0.25,
+                    Distribution::Pareto {
+                        scale: 20000.0,
+                        shape: 1.5,
+                    },
+                ),
+            ])
+        );
+        assert_eq!(
+            config.font_size,
+            Distribution::Gamma {
+                shape: 4.0,
+                scale: 10000.0,
+            }
+        );
+        assert_eq!(
+            config.object_count,
+            Distribution::Weibull {
+                scale: 10.0,
+                shape: 0.8,
+            }
+        );
+        assert_eq!(config.css_size, SamplingConfig::default().css_size);
+        assert_eq!(config.max_object_size, Some(2000000));
+        assert_eq!(config.max_html_size, None);
+        assert_eq!(config.budget, Some(Budget::Ratio(2.5)));
+        let config = SamplingConfig::parse("budget = 300000").unwrap();
+        assert_eq!(config.budget.unwrap().bytes(100000), 300000);
+        assert_eq!(Budget::Ratio(1.5).bytes(100000), 150000);
+
+        for &bad in &[
+            "img lognormal(7, 1)",
+            "image = lognormal(7, 1)",
+            "img = normal(7, 1)",
+            "img = lognormal(7)",
+            "img = lognormal(7, -1)",
+            "img = pareto(0, 1)",
+            "img = mixture(lognormal(7, 1))",
+            "img = mixture(-1 lognormal(7, 1), 2 pareto(1, 1))",
+            "img = mixture(1 lognormal(7, 1)",
+            "max_html = -1",
+            "budget = 0.5x",
+        ] {
+            assert!(SamplingConfig::parse(bad).is_err(), "{}", bad);
+        }
+    }
 }
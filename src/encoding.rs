@@ -0,0 +1,431 @@
+//! Contains compression-aware padding.
+//!
+//! When a server compresses responses, the size observed on the wire is the
+//! size of the encoded object rather than that of the object itself. The
+//! functions in this module compress an object and pad it so that its
+//! *encoded* size matches the target size.
+//!
+//! The object is compressed and flushed to a byte boundary, and the padding
+//! is then appended as uncompressed (stored) blocks, whose encoded size is
+//! known exactly. The padding itself is the same as for uncompressed
+//! objects (e.g., an HTML comment), so the decoded object remains valid.
+use brotli::CompressorWriter;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use rand::Rng;
+use std::io::Write;
+use std::mem;
+
+use objects::*;
+use pad::{get_padding, min_padding_size};
+
+static GZIP_HEADER: &[u8] = &[0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0xff];
+const GZIP_TRAILER_SIZE: usize = 8;
+static ZLIB_HEADER: &[u8] = &[0x78, 0x9c];
+const ZLIB_TRAILER_SIZE: usize = 4;
+const ADLER32_MODULUS: u32 = 65_521;
+
+// A stored deflate block has a 1 byte header (once byte-aligned) followed by
+// 2 bytes of length and 2 bytes of one's complement of the length.
+const DEFLATE_STORED_HEADER_SIZE: usize = 5;
+const DEFLATE_STORED_MAX_SIZE: usize = 65_535;
+
+// An uncompressed brotli meta-block with 4 or 5 length nibbles has a 3 byte
+// header, and the stream is closed by a 1 byte empty last meta-block.
+const BROTLI_UNCOMPRESSED_HEADER_SIZE: usize = 3;
+const BROTLI_UNCOMPRESSED_MAX_SIZE: usize = 1 << 20;
+const BROTLI_LAST_EMPTY_METABLOCK: u8 = 0x03;
+const BROTLI_QUALITY: u32 = 9;
+const BROTLI_LGWIN: u32 = 22;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Content codings supported by compression-aware padding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContentEncoding {
+    /// gzip file format (RFC 1952)
+    Gzip,
+    /// zlib format (RFC 1950), which HTTP calls "deflate"
+    Deflate,
+    /// Brotli (RFC 7932)
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// Returns the value of the `Content-Encoding` header matching this
+    /// content coding.
+    pub fn header_value(&self) -> &'static str {
+        match *self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+}
+
+/// An object encoded with a content coding.
+pub struct EncodedObject {
+    /// Encoded content, to be sent as the response body
+    pub content: Vec<u8>,
+    /// Content coding used to encode `content`
+    pub encoding: ContentEncoding,
+}
+
+/// Compresses an object and pads it so that its encoded size is
+/// `target_size`.
+///
+/// # Arguments
+///
+/// * `rng` - Random number generator.
+/// * `object` - The object to encode.
+/// * `target_size` - The target size of the encoded object.
+/// * `encoding` - The content coding to use.
+///
+/// # Returns
+/// The encoded object, or Err(()) if `target_size` is smaller than the
+/// compressed object plus the minimum framing and padding overhead.
+pub fn pad_encoded<R: Rng>(
+    rng: &mut R,
+    object: &Object,
+    target_size: usize,
+    encoding: ContentEncoding,
+) -> Result<EncodedObject, ()> {
+    let content = match encoding {
+        ContentEncoding::Gzip => pad_gzip(rng, object, target_size)?,
+        ContentEncoding::Deflate => pad_zlib(rng, object, target_size)?,
+        ContentEncoding::Brotli => pad_brotli(rng, object, target_size)?,
+    };
+    debug_assert_eq!(content.len(), target_size);
+
+    Ok(EncodedObject { content, encoding })
+}
+
+fn pad_gzip<R: Rng>(rng: &mut R, object: &Object, target_size: usize) -> Result<Vec<u8>, ()> {
+    let compressed = deflate_flushed(&object.content)?;
+    let overhead = GZIP_HEADER.len() + compressed.len() + GZIP_TRAILER_SIZE;
+    let stored_len = target_size.checked_sub(overhead).ok_or(())?;
+    let (blocks, padding) = get_stored_padding(rng, object, stored_len, get_deflate_block_split)?;
+
+    let mut crc = Crc::new();
+    crc.update(&object.content);
+    crc.update(&padding);
+
+    let mut encoded = Vec::with_capacity(target_size);
+    encoded.extend(GZIP_HEADER);
+    encoded.extend(compressed);
+    add_deflate_stored_blocks(&mut encoded, &padding, blocks);
+    encoded.extend(&crc.sum().to_le_bytes());
+    encoded.extend(&crc.amount().to_le_bytes());
+    Ok(encoded)
+}
+
+fn pad_zlib<R: Rng>(rng: &mut R, object: &Object, target_size: usize) -> Result<Vec<u8>, ()> {
+    let compressed = deflate_flushed(&object.content)?;
+    let overhead = ZLIB_HEADER.len() + compressed.len() + ZLIB_TRAILER_SIZE;
+    let stored_len = target_size.checked_sub(overhead).ok_or(())?;
+    let (blocks, padding) = get_stored_padding(rng, object, stored_len, get_deflate_block_split)?;
+
+    let checksum = adler32(adler32(1, &object.content), &padding);
+
+    let mut encoded = Vec::with_capacity(target_size);
+    encoded.extend(ZLIB_HEADER);
+    encoded.extend(compressed);
+    add_deflate_stored_blocks(&mut encoded, &padding, blocks);
+    encoded.extend(&checksum.to_be_bytes());
+    Ok(encoded)
+}
+
+fn pad_brotli<R: Rng>(rng: &mut R, object: &Object, target_size: usize) -> Result<Vec<u8>, ()> {
+    let compressed = brotli_flushed(&object.content)?;
+    let overhead = compressed.len() + 1;
+    let stored_len = target_size.checked_sub(overhead).ok_or(())?;
+    let (blocks, padding) = get_stored_padding(rng, object, stored_len, get_brotli_block_split)?;
+
+    let mut encoded = Vec::with_capacity(target_size);
+    encoded.extend(compressed);
+    add_brotli_uncompressed_metablocks(&mut encoded, &padding, blocks);
+    encoded.push(BROTLI_LAST_EMPTY_METABLOCK);
+    Ok(encoded)
+}
+
+/// Returns the number of uncompressed blocks taking `stored_len` bytes in
+/// total, headers included, and the padding they hold.
+///
+/// `split` maps `stored_len` to the number of blocks and the length of the
+/// data these blocks can hold.
+fn get_stored_padding<R: Rng, F>(
+    rng: &mut R,
+    object: &Object,
+    stored_len: usize,
+    split: F,
+) -> Result<(usize, Vec<u8>), ()>
+where
+    F: Fn(usize) -> Option<(usize, usize)>,
+{
+    let (blocks, pad_len) = split(stored_len).ok_or(())?;
+    if pad_len == 0 {
+        return Ok((blocks, Vec::new()));
+    }
+    if pad_len < min_padding_size(&object.kind) {
+        return Err(());
+    }
+    Ok((blocks, get_padding(rng, &object.kind, pad_len)))
+}
+
+/// Returns the number of blocks needed to store `stored_len` bytes, headers
+/// included, and how many of them are data.
+fn get_block_split(stored_len: usize, header_size: usize, max_size: usize) -> (usize, usize) {
+    let block_len = header_size + max_size;
+    let blocks = stored_len.div_ceil(block_len);
+    (blocks, stored_len.saturating_sub(blocks * header_size))
+}
+
+fn get_deflate_block_split(stored_len: usize) -> Option<(usize, usize)> {
+    // The stream is terminated by a final stored block, so there is at least
+    // one (possibly empty) block.
+    let (blocks, data_len) = get_block_split(
+        stored_len,
+        DEFLATE_STORED_HEADER_SIZE,
+        DEFLATE_STORED_MAX_SIZE,
+    );
+    let blocks = blocks.max(1);
+    if stored_len != data_len + blocks * DEFLATE_STORED_HEADER_SIZE {
+        return None;
+    }
+    Some((blocks, data_len))
+}
+
+fn get_brotli_block_split(stored_len: usize) -> Option<(usize, usize)> {
+    // Uncompressed meta-blocks cannot be empty.
+    let (blocks, data_len) = get_block_split(
+        stored_len,
+        BROTLI_UNCOMPRESSED_HEADER_SIZE,
+        BROTLI_UNCOMPRESSED_MAX_SIZE,
+    );
+    if stored_len != data_len + blocks * BROTLI_UNCOMPRESSED_HEADER_SIZE || data_len < blocks {
+        return None;
+    }
+    Some((blocks, data_len))
+}
+
+/// Splits `len` bytes into `blocks` chunks of nearly equal length.
+fn get_chunk_lens(len: usize, blocks: usize) -> Vec<usize> {
+    (0..blocks)
+        .map(|i| len / blocks + if i < len % blocks { 1 } else { 0 })
+        .collect()
+}
+
+/// Compresses `data` into a raw deflate stream, flushed to a byte boundary
+/// but not terminated.
+fn deflate_flushed(data: &[u8]) -> Result<Vec<u8>, ()> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).map_err(|_| ())?;
+    encoder.flush().map_err(|_| ())?;
+    Ok(mem::take(encoder.get_mut()))
+}
+
+/// Compresses `data` into a brotli stream, flushed to a byte boundary but
+/// not terminated.
+fn brotli_flushed(data: &[u8]) -> Result<Vec<u8>, ()> {
+    let mut writer =
+        CompressorWriter::new(Vec::new(), BROTLI_BUFFER_SIZE, BROTLI_QUALITY, BROTLI_LGWIN);
+    writer.write_all(data).map_err(|_| ())?;
+    writer.flush().map_err(|_| ())?;
+    Ok(writer.get_ref().clone())
+}
+
+/// Appends `data` as `blocks` stored deflate blocks, the last of which is
+/// final.
+fn add_deflate_stored_blocks(encoded: &mut Vec<u8>, data: &[u8], blocks: usize) {
+    let mut start = 0;
+    for (i, len) in get_chunk_lens(data.len(), blocks).into_iter().enumerate() {
+        let bfinal = if i == blocks - 1 { 1 } else { 0 };
+        encoded.push(bfinal);
+        encoded.extend(&(len as u16).to_le_bytes());
+        encoded.extend(&(!(len as u16)).to_le_bytes());
+        encoded.extend(&data[start..start + len]);
+        start += len;
+    }
+}
+
+/// Appends `data` as `blocks` uncompressed brotli meta-blocks.
+fn add_brotli_uncompressed_metablocks(encoded: &mut Vec<u8>, data: &[u8], blocks: usize) {
+    let mut start = 0;
+    for len in get_chunk_lens(data.len(), blocks) {
+        // ISLAST (1 bit, unset), MNIBBLES - 4 (2 bits), MLEN - 1 (4 *
+        // MNIBBLES bits), ISUNCOMPRESSED (1 bit, set). A nibble count above
+        // 4 is only valid if the last nibble is non-zero.
+        let nibbles = if len <= 1 << 16 { 4 } else { 5 };
+        let header = ((nibbles - 4) << 1) | ((len as u32 - 1) << 3) | (1 << (3 + 4 * nibbles));
+        encoded.extend(&header.to_le_bytes()[..BROTLI_UNCOMPRESSED_HEADER_SIZE]);
+        encoded.extend(&data[start..start + len]);
+        start += len;
+    }
+}
+
+/// Updates the Adler-32 checksum `adler` with `data`.
+fn adler32(adler: u32, data: &[u8]) -> u32 {
+    let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+    for &byte in data {
+        a = (a + u32::from(byte)) % ADLER32_MODULUS;
+        b = (b + a) % ADLER32_MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use brotli::Decompressor;
+    use flate2::read::{GzDecoder, ZlibDecoder};
+    use rand::distributions::{IndependentSample, Range};
+    use rand::{weak_rng, SeedableRng, XorShiftRng};
+    use std::io::Read;
+
+    fn generate_html() -> Object {
+        let content = "<html><body><p>ALPaCA</p></body></html>\n".repeat(50);
+        Object {
+            kind: ObjectKind::HTML,
+            size: content.len(),
+            content: content.into_bytes(),
+            uri: None,
+            position: None,
+            target_size: None,
+            candidate_set: None,
+        }
+    }
+
+    fn decode(encoded: &EncodedObject) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        match encoded.encoding {
+            ContentEncoding::Gzip => GzDecoder::new(&encoded.content[..])
+                .read_to_end(&mut decoded)
+                .unwrap(),
+            ContentEncoding::Deflate => ZlibDecoder::new(&encoded.content[..])
+                .read_to_end(&mut decoded)
+                .unwrap(),
+            ContentEncoding::Brotli => Decompressor::new(&encoded.content[..], BROTLI_BUFFER_SIZE)
+                .read_to_end(&mut decoded)
+                .unwrap(),
+        };
+        decoded
+    }
+
+    fn _test_pad_encoded(encoding: ContentEncoding, min_overhead: usize) {
+        let object = generate_html();
+        let compressed_len = match encoding {
+            ContentEncoding::Brotli => brotli_flushed(&object.content).unwrap().len(),
+            _ => deflate_flushed(&object.content).unwrap().len(),
+        };
+        let mut rng = weak_rng();
+        let pad_len = Range::new(min_overhead, 200_000).ind_sample(&mut rng);
+        let target_size = compressed_len + pad_len;
+
+        let encoded = pad_encoded(&mut rng, &object, target_size, encoding).unwrap();
+        assert_eq!(encoded.content.len(), target_size);
+        assert_eq!(encoded.encoding, encoding);
+
+        let decoded = decode(&encoded);
+        // The original object has not changed, and is followed by a
+        // (possibly empty) HTML comment.
+        assert_eq!(decoded[..object.content.len()], object.content[..]);
+        if decoded.len() > object.content.len() {
+            assert!(decoded[object.content.len()..].starts_with(b"<!--"));
+            assert!(decoded.ends_with(b"-->"));
+        }
+    }
+
+    #[test]
+    fn test_pad_encoded_gzip() {
+        let min_overhead = GZIP_HEADER.len() + GZIP_TRAILER_SIZE + DEFLATE_STORED_HEADER_SIZE + 7;
+        _test_pad_encoded(ContentEncoding::Gzip, min_overhead);
+    }
+
+    #[test]
+    fn test_pad_encoded_deflate() {
+        let min_overhead = ZLIB_HEADER.len() + ZLIB_TRAILER_SIZE + DEFLATE_STORED_HEADER_SIZE + 7;
+        _test_pad_encoded(ContentEncoding::Deflate, min_overhead);
+    }
+
+    #[test]
+    fn test_pad_encoded_brotli() {
+        let min_overhead = 1 + BROTLI_UNCOMPRESSED_HEADER_SIZE + 7;
+        _test_pad_encoded(ContentEncoding::Brotli, min_overhead);
+    }
+
+    #[test]
+    fn test_pad_encoded_no_padding() {
+        let object = generate_html();
+        let compressed_len = deflate_flushed(&object.content).unwrap().len();
+        let target_size =
+            compressed_len + GZIP_HEADER.len() + GZIP_TRAILER_SIZE + DEFLATE_STORED_HEADER_SIZE;
+        let encoded =
+            pad_encoded(&mut weak_rng(), &object, target_size, ContentEncoding::Gzip).unwrap();
+        assert_eq!(encoded.content.len(), target_size);
+        assert_eq!(decode(&encoded), object.content);
+    }
+
+    #[test]
+    fn test_pad_encoded_seeded() {
+        // Encoding is reproducible given a seeded generator.
+        let object = generate_html();
+        let seed: [u32; 4] = [0, 1, 2, 3];
+        let target_size = object.content.len();
+        let encoded = (0..2)
+            .map(|_| {
+                let mut rng = XorShiftRng::from_seed(seed);
+                pad_encoded(&mut rng, &object, target_size, ContentEncoding::Brotli)
+                    .unwrap()
+                    .content
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(encoded[0], encoded[1]);
+    }
+
+    #[test]
+    fn test_pad_encoded_too_little() {
+        let object = generate_html();
+        let compressed_len = brotli_flushed(&object.content).unwrap().len();
+        let mut rng = weak_rng();
+        // Not enough room for the framing.
+        assert!(pad_encoded(&mut rng, &object, compressed_len, ContentEncoding::Brotli).is_err());
+        // Not enough room for an HTML comment.
+        let target_size = compressed_len + 1 + BROTLI_UNCOMPRESSED_HEADER_SIZE + 3;
+        assert!(pad_encoded(&mut rng, &object, target_size, ContentEncoding::Brotli).is_err());
+    }
+
+    #[test]
+    fn test_get_brotli_block_split() {
+        assert_eq!(get_brotli_block_split(0), Some((0, 0)));
+        assert_eq!(get_brotli_block_split(3), None);
+        assert_eq!(get_brotli_block_split(4), Some((1, 1)));
+        let max_block = BROTLI_UNCOMPRESSED_HEADER_SIZE + BROTLI_UNCOMPRESSED_MAX_SIZE;
+        assert_eq!(
+            get_brotli_block_split(max_block),
+            Some((1, BROTLI_UNCOMPRESSED_MAX_SIZE))
+        );
+        // Just over one full block, so the data is split over two blocks.
+        assert_eq!(
+            get_brotli_block_split(max_block + 4),
+            Some((2, BROTLI_UNCOMPRESSED_MAX_SIZE + 1))
+        );
+    }
+
+    #[test]
+    fn test_get_deflate_block_split() {
+        // The final block is always present.
+        assert_eq!(get_deflate_block_split(0), None);
+        assert_eq!(get_deflate_block_split(5), Some((1, 0)));
+        let max_block = DEFLATE_STORED_HEADER_SIZE + DEFLATE_STORED_MAX_SIZE;
+        assert_eq!(
+            get_deflate_block_split(max_block + 1),
+            Some((2, DEFLATE_STORED_MAX_SIZE - 4))
+        );
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(1, b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(adler32(1, b"Wiki"), b"pedia"), 0x11E6_0398);
+    }
+}
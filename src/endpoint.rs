@@ -0,0 +1,600 @@
+//! Serves the padding objects referenced by morphed pages.
+//!
+//! Requests for padding objects are answered with a synthetic body of the
+//! requested size, so that the server needs no file at their path.
+//!
+//! Padding objects look like the page's own objects: they have random names
+//! under the page's asset directories, and valid bodies of the kind their
+//! extension tells.
+use std::convert::TryFrom;
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use rand::Rng;
+
+use auth::{get_target_size, ParamAuth};
+use graph::PageGraph;
+use lookup::ObjectLookup;
+use objects::*;
+use pad::{get_padding, min_padding_size};
+use parsing::{parse_param, parse_request_uri, parse_target_size, TOKEN_PARAM};
+
+// Names of padding objects are random strings of these characters.
+static PADDING_NAME_CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const PADDING_NAME_MIN_LEN: usize = 8;
+const PADDING_NAME_MAX_LEN: usize = 16;
+// Extensions of the padding objects which can be served, by kind.
+static PADDING_IMAGE_EXTENSIONS: &[&str] = &["png", "gif"];
+static PADDING_CSS_EXTENSION: &str = "css";
+const PADDING_EXTENSION_MAX_LEN: usize = 3;
+
+// A transparent 1x1 PNG, made of its signature, header chunk and image data
+// (a single RGBA pixel, preceded by its filter type). A text chunk padding
+// it goes after the header.
+static PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+static PNG_HEADER: &[u8] = b"\x00\x00\x00\x01\x00\x00\x00\x01\x08\x06\x00\x00\x00";
+static PNG_PIXELS: &[u8] = &[0, 0, 0, 0, 0];
+static PNG_TEXT_KEYWORD: &[u8] = b"Comment\x00";
+// Length, type and CRC of a chunk.
+const PNG_CHUNK_OVERHEAD: usize = 12;
+
+// A transparent 1x1 GIF, made of a header (with its color table), an
+// optional graphic control extension making it transparent, and the image.
+static GIF_HEADER: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff";
+static GIF_CONTROL_EXTENSION: &[u8] = b"\x21\xf9\x04\x01\x00\x00\x00\x00";
+static GIF_IMAGE: &[u8] = b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00\x3b";
+// GIF comment extension, ignored by decoders. Some decoders reject comments
+// without any data sub-block.
+static GIF_COMMENT_LABEL: &[u8] = b"\x21\xfe";
+const GIF_COMMENT_MIN_SIZE: usize = 5;
+const GIF_MAX_SUB_BLOCK_SIZE: usize = 255;
+
+/// A padding object, as served to the client.
+pub struct PaddingObject {
+    /// Body of the response.
+    pub content: Vec<u8>,
+    /// Media type of the body, for the `Content-Type` header.
+    pub content_type: &'static str,
+}
+
+/// Returns whether `request` is for a padding object: one at
+/// `ALPACA_PAD_PATH`, or one with a target size (possibly encrypted) which
+/// is not found by `lookup`.
+pub fn is_padding_request<L: ObjectLookup>(request: &str, lookup: &L) -> bool {
+    let path = get_request_path(request);
+    let has_target_size =
+        parse_target_size(request).is_some() || parse_param(request, TOKEN_PARAM).is_some();
+    path == ALPACA_PAD_PATH || (has_target_size && lookup.object_size(path).is_none())
+}
+
+/// Serves a padding object.
+///
+/// The body is an object of the kind its extension tells (a PNG or GIF
+/// image, or a stylesheet). Without a known extension, it is a stylesheet
+/// if the request's `Accept` header asks for CSS, and an image otherwise.
+/// Its size is the one carried by the `alpaca-padding` parameter, which
+/// anyone may set: larger sizes than `max_size` are refused.
+///
+/// # Arguments
+///
+/// `rng` - Random number generator.
+/// `request` - HTTP request for the object, including its headers.
+/// `lookup` - Lookup for the server's objects, which are not padding.
+/// `auth` - Authenticates the `alpaca-padding` parameter, if any.
+/// `max_size` - Largest size to serve (e.g., the `max_object_size` padding
+/// objects are sampled with).
+///
+/// # Returns
+/// The padding object, or None if the request is not for a padding object
+/// of a valid (and authenticated) size, of at most `max_size` bytes.
+pub fn serve_padding<R: Rng, L: ObjectLookup>(
+    rng: &mut R,
+    request: &str,
+    lookup: &L,
+    auth: Option<&ParamAuth>,
+    max_size: usize,
+) -> Option<PaddingObject> {
+    if !is_padding_request(request, lookup) {
+        return None;
+    }
+    let size = get_target_size(request, auth).filter(|&size| size <= max_size)?;
+    let path = get_request_path(request);
+    let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension);
+
+    match extension {
+        "png" => Some(PaddingObject {
+            content: get_png_body(rng, size)?,
+            content_type: "image/png",
+        }),
+        "gif" => Some(PaddingObject {
+            content: get_gif_body(rng, size),
+            content_type: "image/gif",
+        }),
+        _ if extension == PADDING_CSS_EXTENSION || accepts_css(request) => {
+            let content = if size >= min_padding_size(&ObjectKind::CSS) {
+                get_padding(rng, &ObjectKind::CSS, size)
+            } else {
+                vec![b' '; size]
+            };
+            Some(PaddingObject {
+                content,
+                content_type: "text/css",
+            })
+        }
+        _ => Some(PaddingObject {
+            content: get_gif_body(rng, size),
+            content_type: "image/gif",
+        }),
+    }
+}
+
+/// Returns a path for a new padding object referenced by document `parent`
+/// of the graph.
+///
+/// The path has a random name, in the directory of an object of the kind
+/// the document may reference (an image for HTML, a stylesheet for CSS),
+/// and the extension of that kind.
+pub(crate) fn get_padding_path<R: Rng>(rng: &mut R, graph: &PageGraph, parent: usize) -> String {
+    get_padding_path_within(rng, graph, parent, usize::MAX)
+}
+
+/// Returns a path for a new padding object referenced by document `parent`
+/// of the graph, as `get_padding_path` does, of at most `max_len` bytes if
+/// `max_len` is at least `get_padding_path_bound` for the graph's first
+/// document alone: objects in directories too long are not used as models.
+pub(crate) fn get_padding_path_within<R: Rng>(
+    rng: &mut R,
+    graph: &PageGraph,
+    parent: usize,
+    max_len: usize,
+) -> String {
+    let kind = get_padding_kind(graph.nodes[parent].object.kind);
+    let css = kind == ObjectKind::CSS;
+    let assets = get_assets(graph)
+        .into_iter()
+        .filter(|&i| get_path_bound(&graph.nodes[i].path) <= max_len)
+        .collect::<Vec<_>>();
+    let same_kind = assets
+        .iter()
+        .cloned()
+        .filter(|&i| graph.nodes[i].object.kind == kind)
+        .collect::<Vec<_>>();
+    // Fall back to any asset, and to the graph's document itself.
+    let model = rng
+        .choose(&same_kind)
+        .or_else(|| rng.choose(&assets))
+        .map_or(0, |&i| i);
+    let model_path = get_object_path(&graph.nodes[model].path);
+
+    let extension = if css {
+        PADDING_CSS_EXTENSION
+    } else {
+        let model_extension = model_path.rsplit_once('.').map_or("", |(_, e)| e);
+        match PADDING_IMAGE_EXTENSIONS
+            .iter()
+            .find(|&&e| e == model_extension)
+        {
+            Some(extension) => extension,
+            None => rng.choose(PADDING_IMAGE_EXTENSIONS).unwrap(),
+        }
+    };
+    let name_len = rng.gen_range(PADDING_NAME_MIN_LEN, PADDING_NAME_MAX_LEN + 1);
+    let name = (0..name_len)
+        .map(|_| *rng.choose(PADDING_NAME_CHARS).unwrap() as char)
+        .collect::<String>();
+
+    format!("{}{}.{}", get_directory(model_path), name, extension)
+}
+
+/// Returns the kind of the padding objects referenced by documents of kind
+/// `parent_kind`: stylesheets imported by stylesheets, and images otherwise.
+pub(crate) fn get_padding_kind(parent_kind: ObjectKind) -> ObjectKind {
+    match parent_kind {
+        ObjectKind::CSS => ObjectKind::CSS,
+        _ => ObjectKind::IMG,
+    }
+}
+
+/// Returns an upper bound to the length of the paths returned by
+/// `get_padding_path` for documents of the graph.
+pub(crate) fn get_padding_path_bound(graph: &PageGraph) -> usize {
+    (0..graph.nodes.len())
+        .filter(|&i| graph.nodes[i].object.kind != ObjectKind::Alpaca)
+        .map(|i| get_path_bound(&graph.nodes[i].path))
+        .max()
+        .unwrap_or_else(|| get_path_bound("/"))
+}
+
+/// Returns an upper bound to the length of the paths of padding objects
+/// modelled on the object at `path`.
+fn get_path_bound(path: &str) -> usize {
+    get_directory(get_object_path(path)).len()
+        + PADDING_NAME_MAX_LEN
+        + 1
+        + PADDING_EXTENSION_MAX_LEN
+}
+
+/// Returns the objects of the graph other than the page and padding.
+fn get_assets(graph: &PageGraph) -> Vec<usize> {
+    (1..graph.nodes.len())
+        .filter(|&i| graph.nodes[i].object.kind != ObjectKind::Alpaca)
+        .collect()
+}
+
+/// Returns the path of a request, without its query and fragment.
+fn get_request_path(request: &str) -> &str {
+    get_object_path(parse_request_uri(request))
+}
+
+fn get_object_path(path: &str) -> &str {
+    path.split(['?', '#']).next().unwrap_or("")
+}
+
+/// Returns the directory of a path, ending with a slash.
+fn get_directory(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(end) => &path[..end + 1],
+        None => "/",
+    }
+}
+
+/// Returns whether the `Accept` header of an HTTP request asks for CSS, as
+/// browsers' requests for stylesheets do.
+fn accepts_css(request: &str) -> bool {
+    request
+        .lines()
+        .skip(1)
+        .filter_map(|header| header.split_once(':'))
+        .any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("accept") && value.contains("text/css")
+        })
+}
+
+/// Returns a 1x1 GIF of `size` bytes, padded with a comment extension.
+///
+/// Sizes too small for a GIF get random bytes instead.
+fn get_gif_body<R: Rng>(rng: &mut R, size: usize) -> Vec<u8> {
+    // Comments cannot take 1 to 4 bytes: the transparency extension is then
+    // left out.
+    let image_size = GIF_HEADER.len() + GIF_IMAGE.len();
+    let transparent = size >= image_size + GIF_CONTROL_EXTENSION.len()
+        && is_gif_comment_size(size - image_size - GIF_CONTROL_EXTENSION.len());
+    let control_extension = if transparent {
+        GIF_CONTROL_EXTENSION
+    } else {
+        &[]
+    };
+    let image_size = image_size + control_extension.len();
+    if size < image_size || !is_gif_comment_size(size - image_size) {
+        return get_padding(rng, &ObjectKind::Unknown, size);
+    }
+
+    let mut gif = GIF_HEADER.to_vec();
+    gif.extend(get_gif_comment(rng, size - image_size));
+    gif.extend(control_extension);
+    gif.extend(GIF_IMAGE);
+    gif
+}
+
+/// Returns a 1x1 PNG of `size` bytes, padded with a text chunk.
+///
+/// Sizes too small for a PNG, or for its text chunk, get random bytes
+/// instead.
+fn get_png_body<R: Rng>(rng: &mut R, size: usize) -> Option<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(PNG_PIXELS)
+        .expect("Writing to a Vec never fails");
+    let pixels = encoder.finish().expect("Writing to a Vec never fails");
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    png.extend(get_png_chunk(b"IHDR", PNG_HEADER)?);
+    let image_size = png.len() + 2 * PNG_CHUNK_OVERHEAD + pixels.len();
+    let text_size = size.saturating_sub(image_size);
+    if size < image_size
+        || (text_size > 0 && text_size < PNG_CHUNK_OVERHEAD + PNG_TEXT_KEYWORD.len())
+    {
+        return Some(get_padding(rng, &ObjectKind::Unknown, size));
+    }
+    // Chunks hold at most 2^32 - 1 bytes.
+    if text_size.saturating_sub(PNG_CHUNK_OVERHEAD) > u32::MAX as usize {
+        return None;
+    }
+
+    if text_size > 0 {
+        let mut text = PNG_TEXT_KEYWORD.to_vec();
+        let text_len = text_size - PNG_CHUNK_OVERHEAD - PNG_TEXT_KEYWORD.len();
+        text.extend((0..text_len).map(|_| *rng.choose(PADDING_NAME_CHARS).unwrap()));
+        png.extend(get_png_chunk(b"tEXt", &text)?);
+    }
+    png.extend(get_png_chunk(b"IDAT", &pixels)?);
+    png.extend(get_png_chunk(b"IEND", &[])?);
+    Some(png)
+}
+
+/// Returns a PNG chunk of the given type and data, with its length and CRC,
+/// or None if the data is too long for a chunk.
+fn get_png_chunk(chunk_type: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let len = u32::try_from(data.len()).ok()?;
+    let mut crc = Crc::new();
+    crc.update(chunk_type);
+    crc.update(data);
+
+    let mut chunk = len.to_be_bytes().to_vec();
+    chunk.extend(chunk_type);
+    chunk.extend(data);
+    chunk.extend(&crc.sum().to_be_bytes());
+    Some(chunk)
+}
+
+fn is_gif_comment_size(len: usize) -> bool {
+    len == 0 || len >= GIF_COMMENT_MIN_SIZE
+}
+
+/// Returns a GIF comment extension of `len` bytes (none if `len` is 0).
+///
+/// A comment is its label and a terminator, around sub-blocks of 2 to 256
+/// bytes (a length byte and random data).
+fn get_gif_comment<R: Rng>(rng: &mut R, len: usize) -> Vec<u8> {
+    assert!(is_gif_comment_size(len), "Invalid GIF comment size");
+    let mut comment = Vec::new();
+    if len == 0 {
+        return comment;
+    }
+
+    comment.extend(GIF_COMMENT_LABEL);
+    let mut left = len - GIF_COMMENT_LABEL.len() - 1;
+    while left > 0 {
+        // Never leave a single byte, which no sub-block can take.
+        let mut block = left.min(GIF_MAX_SUB_BLOCK_SIZE + 1);
+        if left - block == 1 {
+            block -= 1;
+        }
+        comment.push((block - 1) as u8);
+        comment.extend(get_padding(rng, &ObjectKind::Unknown, block - 1));
+        left -= block;
+    }
+    comment.push(0);
+    comment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use auth::Unauthenticated;
+    use gif::DecodeOptions;
+    use rand::{SeedableRng, XorShiftRng};
+
+    const MAX_SIZE: usize = 100_000;
+
+    fn init_seeded_rng() -> XorShiftRng {
+        XorShiftRng::from_seed([0, 1, 2, 3])
+    }
+
+    fn generate_lookup() -> HashMap<String, Vec<u8>> {
+        let mut lookup = HashMap::new();
+        lookup.insert("/css/style.css".to_string(), b"@import 'a.css';".to_vec());
+        lookup.insert("/css/a.css".to_string(), b"p {}".to_vec());
+        lookup.insert("/img/bg.png".to_string(), vec![0u8; 500]);
+        lookup.insert("/img/x.jpg".to_string(), vec![0u8; 500]);
+        lookup
+    }
+
+    #[test]
+    fn test_is_padding_request() {
+        let lookup = generate_lookup();
+        assert!(is_padding_request(
+            "GET /alpaca.pad?alpaca-padding=300 HTTP/1.1",
+            &lookup
+        ));
+        assert!(is_padding_request("/alpaca.pad", &lookup));
+        assert!(is_padding_request(
+            "/img/k2x8a0q1.png?alpaca-padding=300",
+            &lookup
+        ));
+        assert!(!is_padding_request(
+            "/img/bg.png?alpaca-padding=300",
+            &lookup
+        ));
+        assert!(!is_padding_request("/img/k2x8a0q1.png", &lookup));
+        assert!(!is_padding_request("/alpaca.padding", &lookup));
+    }
+
+    #[test]
+    fn test_serve_padding_image() {
+        let lookup = generate_lookup();
+        let mut rng = init_seeded_rng();
+        let request = "GET /alpaca.pad?alpaca-padding=5000 HTTP/1.1\r\n\
+                       Accept: image/avif,image/webp,*/*\r\n";
+        let object = serve_padding(&mut rng, request, &lookup, None, MAX_SIZE).unwrap();
+        assert_eq!(object.content.len(), 5000);
+        assert_eq!(object.content_type, "image/gif");
+        assert!(object.content.starts_with(b"GIF89a"));
+
+        // Disguised padding objects are served as their extension tells.
+        let request = "GET /img/k2x8a0q1.png?alpaca-padding=5000 HTTP/1.1\r\n";
+        let object = serve_padding(&mut rng, request, &lookup, None, MAX_SIZE).unwrap();
+        assert_eq!(object.content.len(), 5000);
+        assert_eq!(object.content_type, "image/png");
+        let object = serve_padding(
+            &mut rng,
+            "/k2x8a0q1.gif?alpaca-padding=90",
+            &lookup,
+            None,
+            MAX_SIZE,
+        )
+        .unwrap();
+        assert_eq!(object.content.len(), 90);
+        assert_eq!(object.content_type, "image/gif");
+    }
+
+    #[test]
+    fn test_serve_padding_css() {
+        let lookup = generate_lookup();
+        let mut rng = init_seeded_rng();
+        let request = "GET /alpaca.pad?alpaca-padding=700 HTTP/1.1\r\n\
+                       Host: example.com\r\n\
+                       accept: text/css,*/*;q=0.1\r\n";
+        let object = serve_padding(&mut rng, request, &lookup, None, MAX_SIZE).unwrap();
+        assert_eq!(object.content.len(), 700);
+        assert!(object.content.starts_with(b"/*"));
+        assert!(object.content.ends_with(b"*/"));
+        assert_eq!(object.content_type, "text/css");
+
+        let request = "GET /alpaca.pad?alpaca-padding=3 HTTP/1.1\r\nAccept: text/css\r\n";
+        assert_eq!(
+            serve_padding(&mut rng, request, &lookup, None, MAX_SIZE)
+                .unwrap()
+                .content,
+            b"   "
+        );
+        let request = "/css/k2x8a0q1.css?alpaca-padding=700";
+        let object = serve_padding(&mut rng, request, &lookup, None, MAX_SIZE).unwrap();
+        assert_eq!(object.content.len(), 700);
+        assert_eq!(object.content_type, "text/css");
+    }
+
+    #[test]
+    fn test_serve_padding_invalid() {
+        let lookup = generate_lookup();
+        let mut rng = init_seeded_rng();
+        assert!(serve_padding(
+            &mut rng,
+            "/img/bg.png?alpaca-padding=300",
+            &lookup,
+            None,
+            MAX_SIZE
+        )
+        .is_none());
+        assert!(serve_padding(&mut rng, "/alpaca.pad", &lookup, None, MAX_SIZE).is_none());
+        assert!(serve_padding(
+            &mut rng,
+            "/alpaca.pad?alpaca-padding=x",
+            &lookup,
+            None,
+            MAX_SIZE
+        )
+        .is_none());
+
+        // Sizes which are not authenticated are refused, or clamped.
+        let request = "/alpaca.pad?alpaca-padding=999999";
+        let ttl = Duration::from_secs(60);
+        let auth = ParamAuth::new(b"secret", ttl, Unauthenticated::Refuse);
+        assert!(serve_padding(&mut rng, request, &lookup, Some(&auth), MAX_SIZE).is_none());
+        let auth = ParamAuth::new(b"secret", ttl, Unauthenticated::Clamp(100));
+        let object = serve_padding(&mut rng, request, &lookup, Some(&auth), MAX_SIZE).unwrap();
+        assert_eq!(object.content.len(), 100);
+
+        // Sizes larger than the maximum are refused.
+        let request = "/x.gif?alpaca-padding=18446744073709551615";
+        assert!(serve_padding(&mut rng, request, &lookup, None, MAX_SIZE).is_none());
+        let request = "/x.gif?alpaca-padding=100001";
+        assert!(serve_padding(&mut rng, request, &lookup, None, MAX_SIZE).is_none());
+        let request = "/x.gif?alpaca-padding=100000";
+        assert!(serve_padding(&mut rng, request, &lookup, None, MAX_SIZE).is_some());
+        // As are PNG images too large for their text chunk.
+        let request = "/x.png?alpaca-padding=5000000000";
+        assert!(serve_padding(&mut rng, request, &lookup, None, usize::MAX).is_none());
+    }
+
+    #[test]
+    fn test_get_padding_path() {
+        let lookup = generate_lookup();
+        let html =
+            "<img src=/img/bg.png><img src=/img/x.jpg><link rel=stylesheet href=/css/style.css>";
+        let page = Object::from(html.as_bytes(), "/index.html");
+        let graph = PageGraph::build(&page, &lookup);
+        let bound = get_padding_path_bound(&graph);
+        let mut rng = init_seeded_rng();
+
+        for _ in 0..20 {
+            // Pages get images, in their images' directories.
+            let path = get_padding_path(&mut rng, &graph, 0);
+            let name = path.strip_prefix("/img/").unwrap();
+            let (name, extension) = name.split_once('.').unwrap();
+            assert!(PADDING_IMAGE_EXTENSIONS.contains(&extension));
+            assert!(name.len() >= PADDING_NAME_MIN_LEN && name.len() <= PADDING_NAME_MAX_LEN);
+            assert!(name.bytes().all(|c| PADDING_NAME_CHARS.contains(&c)));
+            assert!(path.len() <= bound);
+            assert!(is_padding_request(
+                &format!("{}?alpaca-padding=1", path),
+                &lookup
+            ));
+
+            // Stylesheets get stylesheets.
+            let path = get_padding_path(&mut rng, &graph, 3);
+            assert!(path.starts_with("/css/") && path.ends_with(".css"));
+            assert!(path.len() <= bound);
+        }
+
+        // Without any object, they go to the document's directory.
+        let page = Object::from(&b"<p>"[..], "/blog/post.html");
+        let graph = PageGraph::build(&page, &lookup);
+        assert!(get_padding_path(&mut rng, &graph, 0).starts_with("/blog/"));
+    }
+
+    #[test]
+    fn test_get_png_body() {
+        let mut rng = init_seeded_rng();
+        for size in (0..120).chain(vec![1000, 70000]) {
+            let png = get_png_body(&mut rng, size).unwrap();
+            assert_eq!(png.len(), size);
+            if !png.starts_with(PNG_SIGNATURE) {
+                // Only a few sizes are too small for a PNG, or for its text.
+                assert!(size < 100);
+                continue;
+            }
+
+            let mut reader = png::Decoder::new(&png[..]).read_info().unwrap();
+            let mut pixels = vec![0; reader.output_buffer_size()];
+            let frame = reader.next_frame(&mut pixels).unwrap();
+            assert_eq!((frame.width, frame.height), (1, 1));
+        }
+        // The text chunk cannot hold more than 2^32 - 1 bytes.
+        assert!(get_png_body(&mut rng, u32::MAX as usize + 100).is_none());
+    }
+
+    #[test]
+    fn test_get_gif_body() {
+        let mut rng = init_seeded_rng();
+        let min_size = GIF_HEADER.len() + GIF_IMAGE.len();
+        for size in (min_size..min_size + 20).chain(vec![300, 301, 302, 5000]) {
+            let gif = get_gif_body(&mut rng, size);
+            assert_eq!(gif.len(), size);
+            if !gif.starts_with(b"GIF89a") {
+                // Only a few sizes are too small for a GIF.
+                assert!(size < min_size + GIF_CONTROL_EXTENSION.len());
+                continue;
+            }
+
+            let mut decoder = DecodeOptions::new().read_info(&gif[..]).unwrap();
+            let frame = decoder.read_next_frame().unwrap().unwrap();
+            assert_eq!((frame.width, frame.height), (1, 1));
+            assert!(decoder.read_next_frame().unwrap().is_none());
+        }
+        assert_eq!(get_gif_body(&mut rng, 10).len(), 10);
+    }
+
+    #[test]
+    fn test_get_gif_comment() {
+        let mut rng = init_seeded_rng();
+        assert!(get_gif_comment(&mut rng, 0).is_empty());
+        for len in [5, 6, 258, 259, 260, 261, 1000] {
+            let comment = get_gif_comment(&mut rng, len);
+            assert_eq!(comment.len(), len);
+            assert_eq!(comment[..2], GIF_COMMENT_LABEL[..]);
+            assert_eq!(comment[len - 1], 0);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_get_gif_comment_invalid() {
+        get_gif_comment(&mut init_seeded_rng(), 4);
+    }
+}
@@ -0,0 +1,331 @@
+//! Models the objects a browser fetches when loading a page.
+//!
+//! Loading a page fetches the objects it references and, in turn, those
+//! referenced by the documents among them (e.g., the fonts and images of a
+//! stylesheet).
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use lookup::ObjectLookup;
+use objects::*;
+use parsing::{parse_objects, resolve_uri};
+
+/// An object fetched when loading a page.
+pub struct Node {
+    /// The object. Its size is the object's size before morphing; its
+    /// content is only loaded for documents.
+    pub object: Object,
+    /// Path (and query) of the object on the server.
+    pub path: String,
+    /// The document which first references the object, or None for the page
+    /// itself.
+    pub parent: Option<usize>,
+    /// The references in the document to other objects, as the reference
+    /// (with its URI and position) and the node it refers to.
+    pub refs: Vec<(Object, usize)>,
+}
+
+/// The dependency graph of a page load.
+///
+/// Browsers fetch an object once per page load, so an object referenced
+/// several times is a single node. The first node is the page itself, and
+/// every other node follows its parent. Padding objects are nodes of kind
+/// `Alpaca`, which are not referenced yet.
+pub struct PageGraph {
+    /// The nodes of the graph.
+    pub nodes: Vec<Node>,
+    /// Sets of nodes among which the browser fetches a single one (e.g., the
+    /// candidates of an image's `srcset`).
+    pub candidate_sets: Vec<Vec<usize>>,
+}
+
+impl PageGraph {
+    /// Builds the dependency graph of loading `page`, looking up the objects
+    /// it references with `lookup`.
+    ///
+    /// Objects which cannot be looked up (e.g., those on a different server)
+    /// are not part of the graph.
+    pub fn build<L: ObjectLookup>(page: &Object, lookup: &L) -> PageGraph {
+        let path = page
+            .uri
+            .as_ref()
+            .and_then(|uri| resolve_uri("/", uri))
+            .unwrap_or_else(|| "/".to_string());
+        let root = Node {
+            object: Object {
+                kind: page.kind,
+                content: page.content.clone(),
+                size: page.content.len(),
+                uri: page.uri.clone(),
+                position: None,
+                target_size: None,
+                candidate_set: None,
+            },
+            path: path.clone(),
+            parent: None,
+            refs: Vec::new(),
+        };
+
+        let mut graph = PageGraph {
+            nodes: vec![root],
+            candidate_sets: Vec::new(),
+        };
+        let mut indices = HashMap::new();
+        indices.insert(path, 0);
+
+        // Nodes are appended as they are found, so this visits the graph
+        // breadth first.
+        let mut i = 0;
+        while i < graph.nodes.len() {
+            if graph.is_document(i) {
+                graph.nodes[i].refs = graph.lookup_refs(i, &mut indices, lookup);
+            }
+            i += 1;
+        }
+
+        graph
+    }
+
+    /// Looks up the objects referenced by document `i`, appending those not
+    /// yet in the graph.
+    fn lookup_refs<L: ObjectLookup>(
+        &mut self,
+        i: usize,
+        indices: &mut HashMap<String, usize>,
+        lookup: &L,
+    ) -> Vec<(Object, usize)> {
+        let mut refs = Vec::new();
+        let mut candidate_sets = BTreeMap::new();
+
+        for reference in parse_objects(&self.nodes[i].object) {
+            let path = match reference
+                .uri
+                .as_ref()
+                .and_then(|uri| resolve_uri(&self.nodes[i].path, uri))
+            {
+                Some(path) => path,
+                None => continue,
+            };
+            if let Some(&j) = indices.get(&path) {
+                if let Some(set) = reference.candidate_set {
+                    candidate_sets.entry(set).or_insert_with(Vec::new).push(j);
+                }
+                refs.push((reference, j));
+                continue;
+            }
+            let size = match lookup.object_size(&path) {
+                Some(size) => size,
+                None => continue,
+            };
+            let content = if is_document_kind(reference.kind) {
+                lookup.object_content(&path).unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+
+            let j = self.nodes.len();
+            self.nodes.push(Node {
+                object: Object {
+                    kind: reference.kind,
+                    content,
+                    size,
+                    uri: Some(path.clone()),
+                    position: None,
+                    target_size: None,
+                    candidate_set: None,
+                },
+                path: path.clone(),
+                parent: Some(i),
+                refs: Vec::new(),
+            });
+            indices.insert(path, j);
+            if let Some(set) = reference.candidate_set {
+                candidate_sets.entry(set).or_insert_with(Vec::new).push(j);
+            }
+            refs.push((reference, j));
+        }
+
+        self.candidate_sets.extend(candidate_sets.into_values());
+        refs
+    }
+
+    /// Returns whether node `i` is a document, which references objects of
+    /// its own.
+    pub fn is_document(&self, i: usize) -> bool {
+        is_document_kind(self.nodes[i].object.kind)
+    }
+
+    /// Returns the nodes reachable from node `i`, starting with `i` itself.
+    ///
+    /// The nodes are in the order they would have in the graph of loading
+    /// node `i` on its own.
+    pub fn reachable(&self, i: usize) -> Vec<usize> {
+        let mut nodes = vec![i];
+        let mut seen = HashSet::new();
+        seen.insert(i);
+
+        let mut k = 0;
+        while k < nodes.len() {
+            for &(_, j) in &self.nodes[nodes[k]].refs {
+                if seen.insert(j) {
+                    nodes.push(j);
+                }
+            }
+            k += 1;
+        }
+
+        nodes
+    }
+
+    /// Returns the padding objects referenced by document `i`.
+    pub fn padding(&self, i: usize) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|&j| {
+                self.nodes[j].object.kind == ObjectKind::Alpaca && self.nodes[j].parent == Some(i)
+            })
+            .collect()
+    }
+
+    /// Adds a padding object of size `target_size` at `path`, to be
+    /// referenced by document `parent`.
+    ///
+    /// # Returns
+    /// The node of the padding object.
+    pub fn add_padding(&mut self, parent: usize, path: String, target_size: usize) -> usize {
+        self.nodes.push(Node {
+            object: Object {
+                kind: ObjectKind::Alpaca,
+                content: Vec::new(),
+                size: 0,
+                uri: Some(path.clone()),
+                position: None,
+                target_size: Some(target_size),
+                candidate_set: None,
+            },
+            path,
+            parent: Some(parent),
+            refs: Vec::new(),
+        });
+        self.nodes.len() - 1
+    }
+}
+
+pub(crate) fn is_document_kind(kind: ObjectKind) -> bool {
+    matches!(kind, ObjectKind::HTML | ObjectKind::CSS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate_lookup() -> HashMap<String, Vec<u8>> {
+        let mut lookup = HashMap::new();
+        lookup.insert(
+            "/css/style.css".to_string(),
+            b"@import 'fonts.css'; body { background: url(/img/bg.png); }".to_vec(),
+        );
+        lookup.insert(
+            "/css/fonts.css".to_string(),
+            b"@import 'style.css'; @font-face { src: url(../f.woff); }".to_vec(),
+        );
+        lookup.insert("/img/bg.png".to_string(), vec![0u8; 500]);
+        lookup.insert("/f.woff".to_string(), vec![0u8; 800]);
+        lookup.insert("/a.png".to_string(), vec![0u8; 1000]);
+        lookup
+    }
+
+    static HTML: &str = "<link rel=stylesheet href=css/style.css><img src=img/bg.png>\
+                         <img src=/a.png><img src=/missing.png><img src=//cdn.com/x.png>";
+
+    fn paths(graph: &PageGraph, nodes: &[usize]) -> Vec<String> {
+        nodes.iter().map(|&i| graph.nodes[i].path.clone()).collect()
+    }
+
+    #[test]
+    fn test_build() {
+        let lookup = generate_lookup();
+        let page = Object::from(HTML.as_bytes(), "GET /index.html HTTP/1.1");
+        let graph = PageGraph::build(&page, &lookup);
+
+        let all = (0..graph.nodes.len()).collect::<Vec<_>>();
+        assert_eq!(
+            paths(&graph, &all),
+            vec![
+                "/index.html",
+                "/css/style.css",
+                "/img/bg.png",
+                "/a.png",
+                "/css/fonts.css",
+                "/f.woff"
+            ]
+        );
+        let parents = graph.nodes.iter().map(|n| n.parent).collect::<Vec<_>>();
+        assert_eq!(
+            parents,
+            vec![None, Some(0), Some(0), Some(0), Some(1), Some(4)]
+        );
+        // Objects are fetched once, even if referenced by several documents
+        // (or cyclically).
+        let refs = |i: usize| graph.nodes[i].refs.iter().map(|r| r.1).collect::<Vec<_>>();
+        assert_eq!(refs(0), vec![1, 2, 3]);
+        assert_eq!(refs(1), vec![4, 2]);
+        assert_eq!(refs(4), vec![1, 5]);
+        assert!(refs(2).is_empty());
+
+        assert_eq!(graph.nodes[1].object.content, lookup["/css/style.css"]);
+        assert_eq!(graph.nodes[1].object.size, lookup["/css/style.css"].len());
+        assert!(graph.nodes[3].object.content.is_empty());
+        assert_eq!(graph.nodes[3].object.size, 1000);
+    }
+
+    #[test]
+    fn test_candidate_sets() {
+        let mut lookup = generate_lookup();
+        lookup.insert("/a-2x.png".to_string(), vec![0u8; 3000]);
+        let html = "<img src=/a.png srcset=\"/a-2x.png 2x, /missing.png 3x\">\
+                    <picture><source srcset=/img/bg.png><img src=/a.png></picture>";
+        let page = Object::from(html.as_bytes(), "/index.html");
+        let graph = PageGraph::build(&page, &lookup);
+
+        let all = (0..graph.nodes.len()).collect::<Vec<_>>();
+        assert_eq!(
+            paths(&graph, &all),
+            vec!["/index.html", "/a.png", "/a-2x.png", "/img/bg.png"]
+        );
+        assert_eq!(graph.candidate_sets, vec![vec![1, 2], vec![3, 1]]);
+    }
+
+    #[test]
+    fn test_reachable() {
+        let lookup = generate_lookup();
+        let page = Object::from(HTML.as_bytes(), "/index.html");
+        let graph = PageGraph::build(&page, &lookup);
+
+        // The nodes reachable from a stylesheet are those of its own graph.
+        let css = Object::from(&lookup["/css/style.css"], "/css/style.css");
+        let css_graph = PageGraph::build(&css, &lookup);
+        let css_all = (0..css_graph.nodes.len()).collect::<Vec<_>>();
+        assert_eq!(
+            paths(&graph, &graph.reachable(1)),
+            paths(&css_graph, &css_all)
+        );
+        assert_eq!(graph.reachable(0).len(), graph.nodes.len());
+        assert_eq!(graph.reachable(3), vec![3]);
+    }
+
+    #[test]
+    fn test_padding() {
+        let lookup = generate_lookup();
+        let page = Object::from(HTML.as_bytes(), "/index.html");
+        let mut graph = PageGraph::build(&page, &lookup);
+        let n = graph.nodes.len();
+
+        assert_eq!(graph.add_padding(0, ALPACA_PAD_PATH.to_string(), 300), n);
+        assert_eq!(graph.add_padding(1, "/img/x1.png".to_string(), 400), n + 1);
+        assert_eq!(graph.padding(0), vec![n]);
+        assert_eq!(graph.padding(1), vec![n + 1]);
+        assert!(!graph.is_document(n));
+        assert_eq!(graph.nodes[n].path, ALPACA_PAD_PATH);
+        // Padding objects are not referenced by documents yet.
+        assert_eq!(graph.reachable(0).len(), n);
+    }
+}
@@ -3,11 +3,38 @@
 //! A library to implement the ALPaCA defense to Website Fingerprinting
 //! attacks.
 #![warn(missing_docs)]
+// Sampling and morphing failures carry no further information.
+#![allow(clippy::result_unit_err)]
 
+extern crate base64;
+extern crate brotli;
+extern crate chacha20poly1305;
+extern crate flate2;
+extern crate hmac;
 extern crate rand;
+extern crate sha2;
+
+#[cfg(test)]
+extern crate gif;
+#[cfg(test)]
+extern crate png;
+#[cfg(test)]
+extern crate roxmltree;
 
 pub mod pad;
+pub mod encoding;
 pub mod objects;
 pub mod parsing;
+pub mod auth;
+pub mod lookup;
+pub mod graph;
+pub mod cache;
+pub mod placement;
+pub mod report;
 pub mod morphing;
+pub mod streaming;
+pub mod endpoint;
 pub mod distribution;
+pub mod rng;
+#[cfg(feature = "async")]
+pub mod asynchronous;
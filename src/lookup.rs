@@ -0,0 +1,116 @@
+//! Provides lookups of the objects referenced by a page.
+//!
+//! Morphing a page requires the size of each object it references, and the
+//! content of those objects which in turn reference others (e.g., CSS).
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// When ObjectLookup is implemented for a data type, it can be used to find
+/// the objects referenced by a page.
+///
+/// Objects are identified by their path on the server (e.g., "/img.png"),
+/// possibly followed by a query string.
+pub trait ObjectLookup {
+    /// Returns the size of the object at `uri`, or None if there is no such
+    /// object.
+    fn object_size(&self, uri: &str) -> Option<usize>;
+
+    /// Returns the content of the object at `uri`, or None if there is no
+    /// such object.
+    fn object_content(&self, uri: &str) -> Option<Vec<u8>>;
+}
+
+/// Looks up objects as files under a web server's document root.
+pub struct DocumentRoot {
+    root: PathBuf,
+}
+
+impl DocumentRoot {
+    /// Construct a DocumentRoot given the path of the document root.
+    pub fn new<P: Into<PathBuf>>(root: P) -> DocumentRoot {
+        DocumentRoot { root: root.into() }
+    }
+
+    /// Returns the path of the file serving `uri`, or None if it would be
+    /// outside of the document root.
+    fn file_path(&self, uri: &str) -> Option<PathBuf> {
+        let path = Path::new(strip_query(uri).trim_start_matches('/'));
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return None;
+        }
+        Some(self.root.join(path))
+    }
+}
+
+impl ObjectLookup for DocumentRoot {
+    fn object_size(&self, uri: &str) -> Option<usize> {
+        let metadata = fs::metadata(self.file_path(uri)?).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        Some(metadata.len() as usize)
+    }
+
+    fn object_content(&self, uri: &str) -> Option<Vec<u8>> {
+        fs::read(self.file_path(uri)?).ok()
+    }
+}
+
+/// Looks up objects in memory, keyed by their path.
+impl ObjectLookup for HashMap<String, Vec<u8>> {
+    fn object_size(&self, uri: &str) -> Option<usize> {
+        self.get(strip_query(uri)).map(|c| c.len())
+    }
+
+    fn object_content(&self, uri: &str) -> Option<Vec<u8>> {
+        self.get(strip_query(uri)).cloned()
+    }
+}
+
+fn strip_query(uri: &str) -> &str {
+    uri.split(['?', '#']).next().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_document_root() {
+        let root = env::temp_dir().join(format!("alpaca-lookup-{}", process::id()));
+        fs::create_dir_all(root.join("css")).unwrap();
+        fs::write(root.join("css/style.css"), b"p {}").unwrap();
+
+        let lookup = DocumentRoot::new(&root);
+        assert_eq!(lookup.object_size("/css/style.css"), Some(4));
+        assert_eq!(lookup.object_size("/css/style.css?v=1"), Some(4));
+        assert_eq!(
+            lookup.object_content("/css/./style.css"),
+            Some(b"p {}".to_vec())
+        );
+        // Directories and missing files are not objects.
+        assert_eq!(lookup.object_size("/css"), None);
+        assert_eq!(lookup.object_size("/missing.png"), None);
+        // Nothing outside of the document root can be looked up.
+        assert_eq!(lookup.file_path("/../etc/passwd"), None);
+        assert_eq!(lookup.file_path("/css/../../etc/passwd"), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_hash_map() {
+        let mut lookup = HashMap::new();
+        lookup.insert("/img.png".to_string(), vec![0u8; 300]);
+        assert_eq!(lookup.object_size("/img.png?alpaca-padding=400"), Some(300));
+        assert_eq!(lookup.object_content("/img.png#top"), Some(vec![0u8; 300]));
+        assert_eq!(lookup.object_size("/other.png"), None);
+    }
+}
@@ -1,9 +1,13 @@
 //! Defines object data model used by libalpaca.
-use parsing::parse_object_kind;
+use parsing::{parse_object_kind, parse_request_uri};
+
+/// Path at which the server serves padding objects (ALPACA_PAD_PATH), besides
+/// the paths morphed pages give them.
+pub static ALPACA_PAD_PATH: &str = "/alpaca.pad";
 
 /// Defines our basic object types, each of which has a corresponding
 /// unique (distribution, padding type) tuple.
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ObjectKind {
     /// Fake "padding" object
     Alpaca,
@@ -13,6 +17,20 @@ pub enum ObjectKind {
     CSS,
     /// IMG: PNG, JPEG, etc.
     IMG,
+    /// SVG image, which is an XML document
+    SVG,
+    /// JavaScript
+    JS,
+    /// Font: WOFF, TrueType, etc.
+    Font,
+    /// JSON document
+    JSON,
+    /// Plain text
+    Text,
+    /// MP4: ISO base media file format video or audio
+    MP4,
+    /// WebM: Matroska video or audio
+    WebM,
     /// Used when our parser cannot determine the object type
     Unknown,
 }
@@ -23,10 +41,19 @@ pub struct Object {
     pub kind: ObjectKind,
     /// Content (Vector of bytes) of the Object
     pub content: Vec<u8>,
-    /// Position in the HTML body
+    /// Size of the Object before padding. For referenced objects, whose
+    /// content is not loaded, this is looked up separately.
+    pub size: usize,
+    /// URI of the Object, as written in the document referencing it
+    pub uri: Option<String>,
+    /// Position of the Object's URI in the document (HTML or CSS)
+    /// referencing it
     pub position: Option<usize>,
     /// Size to pad the Object to
     pub target_size: Option<usize>,
+    /// Set of references in the document referencing the Object among which
+    /// the browser fetches a single one (e.g., the candidates of `srcset`)
+    pub candidate_set: Option<usize>,
 }
 
 impl Object {
@@ -35,8 +62,11 @@ impl Object {
         Object {
             kind: parse_object_kind(raw, request),
             content: raw.to_vec(),
+            size: raw.len(),
+            uri: Some(parse_request_uri(request).to_string()),
             position: None,
             target_size: None,
+            candidate_set: None,
         }
     }
 
@@ -63,19 +93,19 @@ mod tests {
         let object = Object {
             kind: ObjectKind::IMG,
             content: raw.to_vec(),
+            size: raw_len,
+            uri: None,
             position: None,
             target_size: None,
+            candidate_set: None,
         };
         assert_eq!(object.content.len(), raw_len);
-        assert!(match object.kind {
-            ObjectKind::IMG => true,
-            _ => false,
-        });
+        assert!(matches!(object.kind, ObjectKind::IMG));
 
         let obj_ptr = object.as_ptr();
         unsafe {
-            for i in 0..raw_len {
-                assert_eq!(raw[i], *obj_ptr.offset(i as isize));
+            for (i, b) in raw.iter().enumerate() {
+                assert_eq!(*b, *obj_ptr.add(i));
             }
         }
     }
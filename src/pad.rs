@@ -1,18 +1,29 @@
 //! Contains padding functions for different resource types.
-use rand::{weak_rng, Rng};
+use rand::Rng;
 use rand::distributions::{IndependentSample, Range};
 use std::iter::Extend;
 
 use objects::*;
 
-static CSS_COMMENT_START: &'static str = "/*";
+static CSS_COMMENT_START: &str = "/*";
 const CSS_COMMENT_START_SIZE: usize = 2;
-static CSS_COMMENT_END: &'static str = "*/";
+static CSS_COMMENT_END: &str = "*/";
 const CSS_COMMENT_END_SIZE: usize = 2;
-static HTML_COMMENT_START: &'static str = "<!--";
+static HTML_COMMENT_START: &str = "<!--";
 const HTML_COMMENT_START_SIZE: usize = 4;
-static HTML_COMMENT_END: &'static str = "-->";
+static HTML_COMMENT_END: &str = "-->";
 const HTML_COMMENT_END_SIZE: usize = 3;
+// Insignificant whitespace as defined by RFC 8259, section 2.
+static JSON_WHITESPACE: &[u8] = b" \t\n\r";
+static TEXT_WHITESPACE: &[u8] = b" \t\n";
+// ISO/IEC 14496-12 `free` box, ignored by MP4 demuxers.
+static MP4_FREE_BOX_TYPE: &[u8] = b"free";
+const MP4_BOX_HEADER_SIZE: usize = 8;
+const MP4_LARGE_BOX_HEADER_SIZE: usize = 16;
+// EBML `Void` element, ignored by Matroska/WebM demuxers.
+const EBML_VOID_ID: u8 = 0xEC;
+const EBML_MIN_ELEMENT_SIZE: usize = 2;
+const EBML_MAX_SIZE_LEN: usize = 8;
 
 /// When Paddable is implemented for a data type, we can pad objects of that
 /// data type.
@@ -21,8 +32,13 @@ pub trait Paddable {
     ///
     /// # Arguments
     ///
+    /// * `rng` - Random number generator.
     /// * `target_size` - The target size.
-    fn pad(&mut self, usize);
+    ///
+    /// # Returns
+    /// Err if the object cannot be padded to `target_size` (see `can_pad`),
+    /// in which case it is left unchanged.
+    fn pad<R: Rng>(&mut self, rng: &mut R, target_size: usize) -> Result<(), ()>;
 }
 
 impl Paddable for Object {
@@ -30,26 +46,212 @@ impl Paddable for Object {
     ///
     /// Padding varies with respect to the object's type.
     /// In HTML and CSS objects, padding is added within a comment.
+    /// In JSON and plain text objects, padding is trailing whitespace, so
+    /// that JSON documents remain parseable.
+    /// In MP4 and WebM objects, padding is an appended `free` box or `Void`
+    /// element respectively, so that media remains playable.
     /// In other (binary) objects it is done by appending random bytes.
     ///
     /// # Arguments
     ///
+    /// * `rng` - Random number generator.
     /// * `target_size` - The target size.
-    fn pad(&mut self, target_size: usize) {
-        // Rust's type system guarantees pad_len will be >=0 because
-        // target_size is unsigned. However, Rust panic!s in this case and in
-        // the future we should do proper recovery/ error handling.
+    fn pad<R: Rng>(&mut self, rng: &mut R, target_size: usize) -> Result<(), ()> {
+        if !can_pad(&self.kind, self.content.len(), target_size) {
+            return Err(());
+        }
         let pad_len = target_size - self.content.len();
-        let padding = match self.kind {
-            ObjectKind::HTML => get_html_padding(pad_len),
-            ObjectKind::CSS => get_css_padding(pad_len),
-            _ => get_binary_padding(pad_len),
+        if pad_len > 0 {
+            let padding = get_padding(rng, &self.kind, pad_len);
+            self.content.extend(padding);
+        }
+        Ok(())
+    }
+}
+
+/// Padding of an object, generated in chunks.
+///
+/// A server can send an object's body unchanged as it streams it, followed
+/// by the padding, without holding either in memory. The padding is made
+/// as `Paddable::pad` makes it.
+pub struct PaddingStream<R: Rng> {
+    rng: R,
+    /// Bytes opening the padding (e.g., a comment's start or a box header).
+    header: Vec<u8>,
+    /// Random bytes following the header.
+    filler: Filler,
+    filler_len: usize,
+    /// Bytes closing the padding (e.g., a comment's end).
+    trailer: &'static [u8],
+    /// Bytes of padding generated so far.
+    position: usize,
+    chunk_size: usize,
+}
+
+// How the random bytes of padding are drawn.
+#[derive(Clone, Copy)]
+enum Filler {
+    // Uniformly from [lb, ub).
+    Range(u8, u8),
+    // Uniformly from a set of characters.
+    Chars(&'static [u8]),
+    // Uniformly from all bytes.
+    Binary,
+}
+
+impl<R: Rng> PaddingStream<R> {
+    /// Construct a PaddingStream padding an object of kind `kind` and size
+    /// `size` up to `target_size`, in chunks of at most `chunk_size` bytes.
+    ///
+    /// # Returns
+    /// The stream, or Err if the object cannot be padded to `target_size`:
+    /// it is smaller than the object, or too close to its size to fit the
+    /// padding's syntax (e.g., an HTML comment).
+    pub fn new(
+        rng: R,
+        kind: &ObjectKind,
+        size: usize,
+        target_size: usize,
+        chunk_size: usize,
+    ) -> Result<PaddingStream<R>, ()> {
+        if !can_pad(kind, size, target_size) || chunk_size == 0 {
+            return Err(());
+        }
+        let pad_len = target_size - size;
+
+        let (header, filler, trailer): (Vec<u8>, Filler, &'static [u8]) = match *kind {
+            _ if pad_len == 0 => (Vec::new(), Filler::Binary, b""),
+            ObjectKind::HTML | ObjectKind::SVG => (
+                Vec::from(HTML_COMMENT_START),
+                Filler::Range(46, 127),
+                HTML_COMMENT_END.as_bytes(),
+            ),
+            ObjectKind::CSS | ObjectKind::JS => (
+                Vec::from(CSS_COMMENT_START),
+                Filler::Range(43, 127),
+                CSS_COMMENT_END.as_bytes(),
+            ),
+            ObjectKind::JSON => (Vec::new(), Filler::Chars(JSON_WHITESPACE), b""),
+            ObjectKind::Text => (Vec::new(), Filler::Chars(TEXT_WHITESPACE), b""),
+            ObjectKind::MP4 => (get_mp4_free_box_header(pad_len), Filler::Binary, b""),
+            ObjectKind::WebM => (get_ebml_void_header(pad_len), Filler::Binary, b""),
+            _ => (Vec::new(), Filler::Binary, b""),
         };
-        self.content.extend(padding);
+        let filler_len = pad_len - header.len() - trailer.len();
+
+        Ok(PaddingStream {
+            rng,
+            header,
+            filler,
+            filler_len,
+            trailer,
+            position: 0,
+            chunk_size,
+        })
+    }
+
+    /// Returns the number of bytes of padding left to generate.
+    pub fn remaining(&self) -> usize {
+        self.header.len() + self.filler_len + self.trailer.len() - self.position
+    }
+}
+
+impl<R: Rng> Iterator for PaddingStream<R> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let len = self.remaining().min(self.chunk_size);
+        if len == 0 {
+            return None;
+        }
+        let mut chunk = Vec::with_capacity(len);
+        let end = self.position + len;
+        let filler_start = self.header.len();
+        let trailer_start = filler_start + self.filler_len;
+
+        if self.position < filler_start {
+            chunk.extend(&self.header[self.position..end.min(filler_start)]);
+        }
+        let filler_len = end
+            .min(trailer_start)
+            .saturating_sub(self.position.max(filler_start));
+        match self.filler {
+            Filler::Range(lb, ub) => {
+                add_random_chars_in_range(&mut self.rng, &mut chunk, filler_len, lb, ub)
+            }
+            Filler::Chars(chars) => {
+                add_random_chars_from(&mut self.rng, &mut chunk, filler_len, chars)
+            }
+            Filler::Binary => chunk.extend(get_binary_padding(&mut self.rng, filler_len)),
+        }
+        if end > trailer_start {
+            let start = self.position.max(trailer_start) - trailer_start;
+            chunk.extend(&self.trailer[start..end - trailer_start]);
+        }
+
+        self.position = end;
+        Some(chunk)
+    }
+}
+
+/// Returns the padding to append to an object of kind `kind` and size
+/// `size` to pad it up to `target_size`, so that a server can send it after
+/// the object's body unchanged.
+///
+/// # Returns
+/// The padding, or Err if the object cannot be padded to `target_size` (see
+/// `PaddingStream::new`).
+pub fn get_padding_suffix<R: Rng>(
+    rng: &mut R,
+    kind: &ObjectKind,
+    size: usize,
+    target_size: usize,
+) -> Result<Vec<u8>, ()> {
+    match target_size.checked_sub(size) {
+        Some(0) => Ok(Vec::new()),
+        Some(pad_len) if can_pad(kind, size, target_size) => Ok(get_padding(rng, kind, pad_len)),
+        _ => Err(()),
     }
 }
 
-fn get_html_padding(pad_len: usize) -> Vec<u8> {
+/// Returns whether an object of kind `kind` and size `size` can be padded
+/// up to `target_size`: it is the object's size, or larger than it by at
+/// least the padding's syntax (e.g., an HTML comment).
+pub(crate) fn can_pad(kind: &ObjectKind, size: usize, target_size: usize) -> bool {
+    match target_size.checked_sub(size) {
+        Some(pad_len) => pad_len == 0 || pad_len >= min_padding_size(kind),
+        None => false,
+    }
+}
+
+/// Returns `pad_len` bytes of padding suitable for an object of kind `kind`.
+pub(crate) fn get_padding<R: Rng>(rng: &mut R, kind: &ObjectKind, pad_len: usize) -> Vec<u8> {
+    match *kind {
+        // XML comments follow the same syntax as HTML comments.
+        ObjectKind::HTML | ObjectKind::SVG => get_html_padding(rng, pad_len),
+        // CSS comments are also JavaScript comments.
+        ObjectKind::CSS | ObjectKind::JS => get_css_padding(rng, pad_len),
+        ObjectKind::JSON => get_json_padding(rng, pad_len),
+        ObjectKind::Text => get_text_padding(rng, pad_len),
+        ObjectKind::MP4 => get_mp4_padding(rng, pad_len),
+        ObjectKind::WebM => get_webm_padding(rng, pad_len),
+        _ => get_binary_padding(rng, pad_len),
+    }
+}
+
+/// Returns the smallest non-zero amount of padding that `get_padding`
+/// accepts for an object of kind `kind`.
+pub(crate) fn min_padding_size(kind: &ObjectKind) -> usize {
+    match *kind {
+        ObjectKind::HTML | ObjectKind::SVG => HTML_COMMENT_START_SIZE + HTML_COMMENT_END_SIZE,
+        ObjectKind::CSS | ObjectKind::JS => CSS_COMMENT_START_SIZE + CSS_COMMENT_END_SIZE,
+        ObjectKind::MP4 => MP4_BOX_HEADER_SIZE,
+        ObjectKind::WebM => EBML_MIN_ELEMENT_SIZE,
+        _ => 0,
+    }
+}
+
+fn get_html_padding<R: Rng>(rng: &mut R, pad_len: usize) -> Vec<u8> {
     // During HTML morphing we should ensure the target size is at least 7
     // bytes larger than the real HTML to account for the comment opening
     // and closing syntax.
@@ -58,12 +260,12 @@ fn get_html_padding(pad_len: usize) -> Vec<u8> {
     // [46,127) contains only human-readable ascii characters, no
     // whitespace, and omits '-' to ensure the HTML comment cannot be ended
     // early by the random generation of the bytes corresponding to '-->'.
-    add_random_chars_in_range(&mut pad, pad_len, 46, 127);
+    add_random_chars_in_range(rng, &mut pad, pad_len, 46, 127);
     pad.extend(Vec::from(HTML_COMMENT_END));
     pad
 }
 
-fn get_css_padding(pad_len: usize) -> Vec<u8> {
+fn get_css_padding<R: Rng>(rng: &mut R, pad_len: usize) -> Vec<u8> {
     // During the CSS morphing we should ensure the target size is at least
     // 4 bytes larger than the real CSS.
     let pad_len = pad_len - CSS_COMMENT_START_SIZE - CSS_COMMENT_END_SIZE;
@@ -71,21 +273,105 @@ fn get_css_padding(pad_len: usize) -> Vec<u8> {
     // [43,127) contains only human-readable ascii characters, no
     // whitespace, and omits '*' to ensure the CSS comment cannot be ended
     // early by the random generation of the bytes corresponding to '*/'.
-    add_random_chars_in_range(&mut pad, pad_len, 43, 127);
+    add_random_chars_in_range(rng, &mut pad, pad_len, 43, 127);
     pad.extend(Vec::from(CSS_COMMENT_END));
     pad
 }
 
-fn add_random_chars_in_range(pad: &mut Vec<u8>, pad_len: usize, lb: u8, ub: u8) {
+fn get_json_padding<R: Rng>(rng: &mut R, pad_len: usize) -> Vec<u8> {
+    // JSON allows any amount of whitespace after the top-level value, so
+    // no minimum size is required.
+    let mut pad = Vec::with_capacity(pad_len);
+    add_random_chars_from(rng, &mut pad, pad_len, JSON_WHITESPACE);
+    pad
+}
+
+fn get_text_padding<R: Rng>(rng: &mut R, pad_len: usize) -> Vec<u8> {
+    let mut pad = Vec::with_capacity(pad_len);
+    add_random_chars_from(rng, &mut pad, pad_len, TEXT_WHITESPACE);
+    pad
+}
+
+fn get_mp4_padding<R: Rng>(rng: &mut R, pad_len: usize) -> Vec<u8> {
+    if pad_len == 0 {
+        return Vec::new();
+    }
+    // During morphing we should ensure the target size is either the real
+    // size or at least 8 bytes larger than it, to fit the box header.
+    let mut pad = get_mp4_free_box_header(pad_len);
+    let payload_len = pad_len - pad.len();
+    pad.extend(get_binary_padding(rng, payload_len));
+    pad
+}
+
+/// Returns the header of a `free` box of `box_len` bytes, header included.
+fn get_mp4_free_box_header(box_len: usize) -> Vec<u8> {
+    assert!(
+        box_len >= MP4_BOX_HEADER_SIZE,
+        "Padding too small for an MP4 box"
+    );
+    let mut header = Vec::with_capacity(MP4_LARGE_BOX_HEADER_SIZE);
+    if box_len <= u32::MAX as usize {
+        header.extend(&(box_len as u32).to_be_bytes());
+        header.extend(MP4_FREE_BOX_TYPE);
+    } else {
+        // A 32-bit size of 1 means a 64-bit size follows the box type.
+        header.extend(&1u32.to_be_bytes());
+        header.extend(MP4_FREE_BOX_TYPE);
+        header.extend(&(box_len as u64).to_be_bytes());
+    }
+    header
+}
+
+fn get_webm_padding<R: Rng>(rng: &mut R, pad_len: usize) -> Vec<u8> {
+    if pad_len == 0 {
+        return Vec::new();
+    }
+    // During morphing we should ensure the target size is either the real
+    // size or at least 2 bytes larger than it, to fit the element header.
+    let mut pad = get_ebml_void_header(pad_len);
+    let data_len = pad_len - pad.len();
+    pad.extend(get_binary_padding(rng, data_len));
+    pad
+}
+
+/// Returns the header (ID and data size) of a `Void` element of
+/// `element_len` bytes, header included.
+fn get_ebml_void_header(element_len: usize) -> Vec<u8> {
+    // Pick the shortest size field that can encode the remaining data
+    // length. A size field of `n` bytes carries 7 * `n` bits of value, and
+    // the all-ones value is reserved for "unknown size".
+    let (size_len, data_len) = (1..EBML_MAX_SIZE_LEN + 1)
+        .filter_map(|n| element_len.checked_sub(1 + n).map(|d| (n, d as u64)))
+        .find(|&(n, d)| d < (1u64 << (7 * n)) - 1)
+        .expect("Padding too small for an EBML element");
+
+    let mut header = vec![EBML_VOID_ID];
+    let size = data_len | (1u64 << (7 * size_len));
+    header.extend(&size.to_be_bytes()[8 - size_len..]);
+    header
+}
+
+fn add_random_chars_from<R: Rng>(rng: &mut R, pad: &mut Vec<u8>, pad_len: usize, chars: &[u8]) {
+    for _ in 0..pad_len {
+        pad.push(*rng.choose(chars).expect("Empty character set"));
+    }
+}
+
+fn add_random_chars_in_range<R: Rng>(
+    rng: &mut R,
+    pad: &mut Vec<u8>,
+    pad_len: usize,
+    lb: u8,
+    ub: u8,
+) {
     let acceptable_chars = Range::new(lb, ub);
-    let mut rng = weak_rng();
     for _ in 0..pad_len {
-        pad.push(acceptable_chars.ind_sample(&mut rng));
+        pad.push(acceptable_chars.ind_sample(rng));
     }
 }
 
-fn get_binary_padding(pad_len: usize) -> Vec<u8> {
-    let mut rng = weak_rng();
+fn get_binary_padding<R: Rng>(rng: &mut R, pad_len: usize) -> Vec<u8> {
     rng.gen_iter::<u8>().take(pad_len).collect()
 }
 
@@ -94,6 +380,7 @@ mod tests {
     use super::*;
 
     use rand::distributions::{IndependentSample, Range};
+    use rand::{weak_rng, SeedableRng, XorShiftRng};
 
     use std::str;
 
@@ -102,35 +389,60 @@ mod tests {
         let mut rng = weak_rng();
         let raw_len = Range::new(0, 50).ind_sample(&mut rng);
         let mut raw = Vec::new();
-        add_random_chars_in_range(&mut raw, raw_len, 46, 127);
+        add_random_chars_in_range(&mut rng, &mut raw, raw_len, 46, 127);
         let mut object = Object {
             kind: ObjectKind::HTML,
             content: raw.to_vec(),
+            size: raw_len,
+            uri: None,
             position: None,
             target_size: None,
+            candidate_set: None,
         };
         assert_eq!(object.content.len(), raw_len);
-        assert!(match object.kind {
-            ObjectKind::HTML => true,
-            _ => false,
-        });
+        assert!(matches!(object.kind, ObjectKind::HTML));
 
         let comment_syntax_size = HTML_COMMENT_START_SIZE + HTML_COMMENT_END_SIZE;
         let pad_len = Range::new(comment_syntax_size, 50).ind_sample(&mut rng);
         let target_size = raw_len + pad_len;
-        object.pad(target_size);
+        object.pad(&mut rng, target_size).unwrap();
         assert_eq!(object.content.len(), target_size);
         _test_html_padding(object.content[raw_len..].to_vec());
         // The original object has not changed.
         assert_eq!(object.content[..raw_len], raw[..])
     }
 
+    #[test]
+    fn test_pad_method_svg() {
+        let mut rng = weak_rng();
+        let raw = b"<?xml version=\"1.0\"?>\n\
+                    <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"10\" height=\"10\">\
+                    <rect width=\"10\" height=\"10\"/></svg>\n";
+        for &pad_len in &[0, 7, 8, 100, 1000] {
+            let mut object = Object {
+                kind: ObjectKind::SVG,
+                content: raw.to_vec(),
+                size: raw.len(),
+                uri: None,
+                position: None,
+                target_size: None,
+                candidate_set: None,
+            };
+            object.pad(&mut rng, raw.len() + pad_len).unwrap();
+            assert_eq!(object.content.len(), raw.len() + pad_len);
+            // The padded image is still a well-formed XML document.
+            let text = str::from_utf8(&object.content).unwrap();
+            let document = roxmltree::Document::parse(text).unwrap();
+            assert_eq!(document.root_element().tag_name().name(), "svg");
+        }
+    }
+
     fn _test_html_padding(padding: Vec<u8>) {
         let mut rng = weak_rng();
         let comment_syntax_size = HTML_COMMENT_START_SIZE + HTML_COMMENT_END_SIZE;
-        let padding = if padding.len() == 0 {
+        let padding = if padding.is_empty() {
             let pad_len = Range::new(comment_syntax_size, 50).ind_sample(&mut rng);
-            let padding = get_html_padding(pad_len);
+            let padding = get_html_padding(&mut rng, pad_len);
             assert_eq!(padding.len(), pad_len);
             padding
         } else {
@@ -165,7 +477,7 @@ mod tests {
         let mut rng = weak_rng();
         let comment_syntax_size = HTML_COMMENT_START_SIZE + HTML_COMMENT_END_SIZE;
         let pad_len = Range::new(0, comment_syntax_size).ind_sample(&mut rng);
-        get_html_padding(pad_len);
+        get_html_padding(&mut rng, pad_len);
     }
 
     #[test]
@@ -173,23 +485,23 @@ mod tests {
         let mut rng = weak_rng();
         let raw_len = Range::new(0, 50).ind_sample(&mut rng);
         let mut raw = Vec::new();
-        add_random_chars_in_range(&mut raw, raw_len, 43, 127);
+        add_random_chars_in_range(&mut rng, &mut raw, raw_len, 43, 127);
         let mut object = Object {
             kind: ObjectKind::CSS,
             content: raw.to_vec(),
+            size: raw_len,
+            uri: None,
             position: None,
             target_size: None,
+            candidate_set: None,
         };
         assert_eq!(object.content.len(), raw_len);
-        assert!(match object.kind {
-            ObjectKind::CSS => true,
-            _ => false,
-        });
+        assert!(matches!(object.kind, ObjectKind::CSS));
 
         let comment_syntax_size = CSS_COMMENT_START_SIZE + CSS_COMMENT_END_SIZE;
         let pad_len = Range::new(comment_syntax_size, 50).ind_sample(&mut rng);
         let target_size = raw_len + pad_len;
-        object.pad(target_size);
+        object.pad(&mut rng, target_size).unwrap();
         assert_eq!(object.content.len(), target_size);
         _test_css_padding(object.content[raw_len..].to_vec());
         // The original object has not changed.
@@ -199,9 +511,9 @@ mod tests {
     fn _test_css_padding(padding: Vec<u8>) {
         let mut rng = weak_rng();
         let comment_syntax_size = CSS_COMMENT_START_SIZE + CSS_COMMENT_END_SIZE;
-        let padding = if padding.len() == 0 {
+        let padding = if padding.is_empty() {
             let pad_len = Range::new(comment_syntax_size, 50).ind_sample(&mut rng);
-            let padding = get_css_padding(pad_len);
+            let padding = get_css_padding(&mut rng, pad_len);
             assert_eq!(padding.len(), pad_len);
             padding
         } else {
@@ -224,6 +536,18 @@ mod tests {
         assert!(!rand_padding.contains(CSS_COMMENT_END));
     }
 
+    #[test]
+    fn test_pad_method_js() {
+        let mut rng = weak_rng();
+        let raw = b"var a = 1;";
+        let mut object = Object::from(raw, "/js/app.js");
+        assert_eq!(object.kind, ObjectKind::JS);
+
+        object.pad(&mut rng, raw.len() + 30).unwrap();
+        assert_eq!(object.content.len(), raw.len() + 30);
+        _test_css_padding(object.content[raw.len()..].to_vec());
+    }
+
     #[test]
     fn test_get_css_padding() {
         let padding = Vec::new();
@@ -236,7 +560,211 @@ mod tests {
         let mut rng = weak_rng();
         let comment_syntax_size = CSS_COMMENT_START_SIZE + CSS_COMMENT_END_SIZE;
         let pad_len = Range::new(0, comment_syntax_size).ind_sample(&mut rng);
-        get_css_padding(pad_len);
+        get_css_padding(&mut rng, pad_len);
+    }
+
+    #[test]
+    fn test_pad_method_json() {
+        let raw = b"{\"alpaca\": [1, 2, 3]}".to_vec();
+        let raw_len = raw.len();
+        let mut object = Object {
+            kind: ObjectKind::JSON,
+            content: raw.to_vec(),
+            size: raw_len,
+            uri: None,
+            position: None,
+            target_size: None,
+            candidate_set: None,
+        };
+
+        let mut rng = weak_rng();
+        let pad_len = Range::new(0, 50).ind_sample(&mut rng);
+        let target_size = raw_len + pad_len;
+        object.pad(&mut rng, target_size).unwrap();
+        assert_eq!(object.content.len(), target_size);
+        // Only insignificant whitespace follows the JSON value.
+        assert!(object.content[raw_len..]
+            .iter()
+            .all(|c| JSON_WHITESPACE.contains(c)));
+        // The original object has not changed.
+        assert_eq!(object.content[..raw_len], raw[..])
+    }
+
+    #[test]
+    fn test_get_json_padding() {
+        let mut rng = weak_rng();
+        let pad_len = Range::new(0, 50).ind_sample(&mut rng);
+        let padding = get_json_padding(&mut rng, pad_len);
+        assert_eq!(padding.len(), pad_len);
+        assert!(padding.iter().all(|c| JSON_WHITESPACE.contains(c)));
+    }
+
+    #[test]
+    fn test_pad_method_text() {
+        let mut rng = weak_rng();
+        let raw_len = Range::new(0, 50).ind_sample(&mut rng);
+        let mut raw = Vec::new();
+        add_random_chars_in_range(&mut rng, &mut raw, raw_len, 33, 127);
+        let mut object = Object {
+            kind: ObjectKind::Text,
+            content: raw.to_vec(),
+            size: raw_len,
+            uri: None,
+            position: None,
+            target_size: None,
+            candidate_set: None,
+        };
+
+        let pad_len = Range::new(0, 50).ind_sample(&mut rng);
+        let target_size = raw_len + pad_len;
+        object.pad(&mut rng, target_size).unwrap();
+        assert_eq!(object.content.len(), target_size);
+        assert!(object.content[raw_len..]
+            .iter()
+            .all(|c| TEXT_WHITESPACE.contains(c)));
+        // The original object has not changed.
+        assert_eq!(object.content[..raw_len], raw[..])
+    }
+
+    #[test]
+    fn test_pad_method_mp4() {
+        let mut rng = weak_rng();
+        let raw_len = Range::new(0, 50).ind_sample(&mut rng);
+        let raw = rng.gen_iter::<u8>().take(raw_len).collect::<Vec<u8>>();
+        let mut object = Object {
+            kind: ObjectKind::MP4,
+            content: raw.to_vec(),
+            size: raw_len,
+            uri: None,
+            position: None,
+            target_size: None,
+            candidate_set: None,
+        };
+
+        let pad_len = Range::new(MP4_BOX_HEADER_SIZE, 50).ind_sample(&mut rng);
+        let target_size = raw_len + pad_len;
+        object.pad(&mut rng, target_size).unwrap();
+        assert_eq!(object.content.len(), target_size);
+        // The padding is a single `free` box spanning all of it.
+        assert_eq!(
+            object.content[raw_len..raw_len + 4],
+            (pad_len as u32).to_be_bytes()
+        );
+        assert_eq!(&object.content[raw_len + 4..raw_len + 8], MP4_FREE_BOX_TYPE);
+        // The original object has not changed.
+        assert_eq!(object.content[..raw_len], raw[..])
+    }
+
+    #[test]
+    fn test_get_mp4_padding() {
+        let mut rng = weak_rng();
+        assert!(get_mp4_padding(&mut rng, 0).is_empty());
+        let pad_len = Range::new(MP4_BOX_HEADER_SIZE, 50).ind_sample(&mut rng);
+        assert_eq!(get_mp4_padding(&mut rng, pad_len).len(), pad_len);
+    }
+
+    #[test]
+    fn test_get_mp4_free_box_header_large() {
+        let box_len = u32::MAX as usize + 1;
+        let header = get_mp4_free_box_header(box_len);
+        assert_eq!(header.len(), MP4_LARGE_BOX_HEADER_SIZE);
+        assert_eq!(header[..4], 1u32.to_be_bytes());
+        assert_eq!(&header[4..8], MP4_FREE_BOX_TYPE);
+        assert_eq!(header[8..], (box_len as u64).to_be_bytes());
+    }
+
+    #[should_panic]
+    #[test]
+    fn test_get_mp4_padding_too_little() {
+        let mut rng = weak_rng();
+        let pad_len = Range::new(1, MP4_BOX_HEADER_SIZE).ind_sample(&mut rng);
+        get_mp4_padding(&mut rng, pad_len);
+    }
+
+    #[test]
+    fn test_pad_method_invalid() {
+        let mut rng = weak_rng();
+        // Targets smaller than the object, or too close to its size for the
+        // padding's syntax, leave it unchanged.
+        for &(kind, target_size) in &[
+            (ObjectKind::MP4, 103),
+            (ObjectKind::MP4, 107),
+            (ObjectKind::WebM, 101),
+            (ObjectKind::HTML, 106),
+            (ObjectKind::CSS, 99),
+            (ObjectKind::IMG, 10),
+        ] {
+            let mut object = Object {
+                kind,
+                content: vec![0u8; 100],
+                size: 100,
+                uri: None,
+                position: None,
+                target_size: None,
+                candidate_set: None,
+            };
+            assert_eq!(object.pad(&mut rng, target_size), Err(()));
+            assert_eq!(object.content, vec![0u8; 100]);
+            // Padding to the object's size leaves it unchanged too.
+            assert_eq!(object.pad(&mut rng, 100), Ok(()));
+            assert_eq!(object.content, vec![0u8; 100]);
+        }
+        assert!(can_pad(&ObjectKind::MP4, 100, 108));
+        assert!(can_pad(&ObjectKind::WebM, 100, 102));
+    }
+
+    #[test]
+    fn test_pad_method_webm() {
+        let mut rng = weak_rng();
+        let raw_len = Range::new(0, 50).ind_sample(&mut rng);
+        let raw = rng.gen_iter::<u8>().take(raw_len).collect::<Vec<u8>>();
+        let mut object = Object {
+            kind: ObjectKind::WebM,
+            content: raw.to_vec(),
+            size: raw_len,
+            uri: None,
+            position: None,
+            target_size: None,
+            candidate_set: None,
+        };
+
+        let pad_len = Range::new(EBML_MIN_ELEMENT_SIZE, 50).ind_sample(&mut rng);
+        let target_size = raw_len + pad_len;
+        object.pad(&mut rng, target_size).unwrap();
+        assert_eq!(object.content.len(), target_size);
+        // The padding is a single `Void` element with a 1-byte size field.
+        assert_eq!(object.content[raw_len], EBML_VOID_ID);
+        assert_eq!(object.content[raw_len + 1], 0x80 | (pad_len - 2) as u8);
+        // The original object has not changed.
+        assert_eq!(object.content[..raw_len], raw[..])
+    }
+
+    #[test]
+    fn test_get_ebml_void_header() {
+        // 1 byte ID + 1 byte size, no data.
+        assert_eq!(get_ebml_void_header(2), vec![EBML_VOID_ID, 0x80]);
+        // 126 data bytes is the largest 1-byte size.
+        assert_eq!(get_ebml_void_header(128), vec![EBML_VOID_ID, 0xFE]);
+        // 127 would be the reserved value, so a 2-byte size is used.
+        assert_eq!(get_ebml_void_header(129), vec![EBML_VOID_ID, 0x40, 0x7E]);
+        assert_eq!(
+            get_ebml_void_header(1 << 20),
+            vec![EBML_VOID_ID, 0x2F, 0xFF, 0xFC]
+        );
+    }
+
+    #[test]
+    fn test_get_webm_padding() {
+        let mut rng = weak_rng();
+        assert!(get_webm_padding(&mut rng, 0).is_empty());
+        let pad_len = Range::new(EBML_MIN_ELEMENT_SIZE, 50000).ind_sample(&mut rng);
+        assert_eq!(get_webm_padding(&mut rng, pad_len).len(), pad_len);
+    }
+
+    #[should_panic]
+    #[test]
+    fn test_get_webm_padding_too_little() {
+        get_webm_padding(&mut weak_rng(), 1);
     }
 
     #[test]
@@ -247,29 +775,122 @@ mod tests {
         let mut object = Object {
             kind: ObjectKind::IMG,
             content: raw.to_vec(),
+            size: raw_len,
+            uri: None,
             position: None,
             target_size: None,
+            candidate_set: None,
         };
         assert_eq!(object.content.len(), raw_len);
-        assert!(match object.kind {
-            ObjectKind::IMG => true,
-            _ => false,
-        });
+        assert!(matches!(object.kind, ObjectKind::IMG));
 
         let pad_len = Range::new(0, 50).ind_sample(&mut rng);
         let target_size = raw_len + pad_len;
-        object.pad(target_size);
+        object.pad(&mut rng, target_size).unwrap();
         assert_eq!(object.content.len(), target_size);
         // The original object has not changed.
         assert_eq!(object.content[..raw_len], raw[..])
     }
 
+    #[test]
+    fn test_pad_method_seeded() {
+        // Padding an object is reproducible given a seeded generator.
+        let seed: [u32; 4] = [0, 1, 2, 3];
+        let padded = (0..2)
+            .map(|_| {
+                let mut object = Object {
+                    kind: ObjectKind::IMG,
+                    content: vec![0u8; 10],
+                    size: 10,
+                    uri: None,
+                    position: None,
+                    target_size: None,
+                    candidate_set: None,
+                };
+                object.pad(&mut XorShiftRng::from_seed(seed), 50).unwrap();
+                object.content
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(padded[0], padded[1]);
+    }
+
+    #[test]
+    fn test_get_padding_seeded() {
+        // Padding is reproducible given a seeded generator.
+        let seed: [u32; 4] = [0, 1, 2, 3];
+        let padding = get_padding(&mut XorShiftRng::from_seed(seed), &ObjectKind::HTML, 50);
+        assert_eq!(
+            padding,
+            get_padding(&mut XorShiftRng::from_seed(seed), &ObjectKind::HTML, 50)
+        );
+    }
+
+    #[test]
+    fn test_padding_stream() {
+        // Streamed padding is the padding `get_padding` makes, in chunks,
+        // or none at all.
+        let seed: [u32; 4] = [0, 1, 2, 3];
+        let kinds = [
+            ObjectKind::HTML,
+            ObjectKind::CSS,
+            ObjectKind::JSON,
+            ObjectKind::Text,
+            ObjectKind::MP4,
+            ObjectKind::WebM,
+            ObjectKind::IMG,
+        ];
+        for kind in &kinds {
+            for &pad_len in &[0, 8, 1000] {
+                let padding = match pad_len {
+                    0 => Vec::new(),
+                    _ => get_padding(&mut XorShiftRng::from_seed(seed), kind, pad_len),
+                };
+                for &chunk_size in &[1, 3, 64, 5000] {
+                    let rng = XorShiftRng::from_seed(seed);
+                    let mut stream =
+                        PaddingStream::new(rng, kind, 100, 100 + pad_len, chunk_size).unwrap();
+                    assert_eq!(stream.remaining(), pad_len);
+                    let mut streamed = Vec::new();
+                    for chunk in &mut stream {
+                        assert!(!chunk.is_empty() && chunk.len() <= chunk_size);
+                        streamed.extend(chunk);
+                    }
+                    assert_eq!(stream.remaining(), 0);
+                    assert_eq!(streamed, padding);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_padding_stream_invalid() {
+        let mut rng = weak_rng();
+        assert!(PaddingStream::new(&mut rng, &ObjectKind::IMG, 100, 99, 10).is_err());
+        assert!(PaddingStream::new(&mut rng, &ObjectKind::HTML, 100, 103, 10).is_err());
+        assert!(PaddingStream::new(&mut rng, &ObjectKind::WebM, 100, 101, 10).is_err());
+        assert!(PaddingStream::new(&mut rng, &ObjectKind::IMG, 100, 200, 0).is_err());
+        assert!(PaddingStream::new(&mut rng, &ObjectKind::HTML, 100, 100, 10).is_ok());
+    }
+
+    #[test]
+    fn test_get_padding_suffix() {
+        let mut rng = weak_rng();
+        let suffix = get_padding_suffix(&mut rng, &ObjectKind::CSS, 100, 150).unwrap();
+        assert_eq!(suffix.len(), 50);
+        assert!(suffix.starts_with(b"/*") && suffix.ends_with(b"*/"));
+        assert!(get_padding_suffix(&mut rng, &ObjectKind::CSS, 100, 102).is_err());
+        assert!(get_padding_suffix(&mut rng, &ObjectKind::CSS, 100, 50).is_err());
+        assert_eq!(
+            get_padding_suffix(&mut rng, &ObjectKind::CSS, 100, 100),
+            Ok(Vec::new())
+        );
+    }
+
     #[test]
     fn test_get_binary_padding() {
         let mut rng = weak_rng();
         let pad_len = Range::new(0, 50).ind_sample(&mut rng);
-        let padding = get_binary_padding(pad_len);
+        let padding = get_binary_padding(&mut rng, pad_len);
         assert_eq!(padding.len(), pad_len);
     }
-
 }
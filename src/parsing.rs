@@ -1,25 +1,836 @@
 //! Contains parsing routines
-use objects::{Object, ObjectKind};
+use objects::{Object, ObjectKind, ALPACA_PAD_PATH};
+
+/// Name of the HTTP GET parameter carrying an object's target size.
+pub static PADDING_PARAM: &str = "alpaca-padding";
+/// Name of the HTTP GET parameter carrying the target sizes of the objects
+/// a document depends on, when morphed as part of a page.
+pub static REFS_PARAM: &str = "alpaca-refs";
+/// Name of the HTTP GET parameter carrying the padding objects of the
+/// documents a document depends on, when morphed as part of a page.
+pub static PADS_PARAM: &str = "alpaca-pads";
+/// Name of the HTTP GET parameter carrying when an object's parameters
+/// expire, in seconds since the Unix epoch.
+pub static EXPIRES_PARAM: &str = "alpaca-expires";
+/// Name of the HTTP GET parameter carrying the MAC of an object's
+/// parameters.
+pub static MAC_PARAM: &str = "alpaca-mac";
+/// Name of the HTTP GET parameter carrying an object's parameters,
+/// encrypted.
+pub static TOKEN_PARAM: &str = "alpaca-token";
+
+// HTML attributes referencing objects, as (tag, attribute) pairs. `link`
+// elements are handled separately, as only some of them are fetched.
+static HTML_REF_ATTRS: &[(&str, &str)] = &[
+    ("img", "src"),
+    ("script", "src"),
+    ("video", "src"),
+    ("video", "poster"),
+    ("audio", "src"),
+    ("source", "src"),
+    ("track", "src"),
+    ("embed", "src"),
+    ("object", "data"),
+    ("input", "src"),
+    ("iframe", "src"),
+    ("frame", "src"),
+];
+// `rel` values of `link` elements whose `href` is fetched.
+static HTML_LINK_RELS: &[&str] = &["stylesheet", "icon", "preload", "prefetch"];
+// Elements whose content is not HTML, and must be skipped when parsing.
+static HTML_RAW_TEXT_TAGS: &[&str] = &["script", "textarea", "title"];
 
 /// Parses the object's kind from its raw representation and
 /// the associated request.
 ///
-/// XXX: if we realise that `request` is not needed to determine
-/// the object's kind, we can remove it from here and from
-/// `objects::Object::from()`.
-#[allow(unused)]
+/// The kind is determined from the extension of the requested path and,
+/// failing that, from the object's leading bytes.
 pub fn parse_object_kind(raw: &[u8], request: &str) -> ObjectKind {
-    unimplemented!();
+    match parse_kind_from_uri(parse_request_uri(request)) {
+        ObjectKind::Unknown => sniff_object_kind(raw),
+        kind => kind,
+    }
 }
 
 /// Parses the target size of an object from its HTTP request.
-#[allow(unused)]
-pub fn parse_target_size(request: &str) -> usize {
-    unimplemented!();
+///
+/// # Returns
+/// The value of the `alpaca-padding` GET parameter, or None if the request
+/// carries no such (valid) parameter.
+pub fn parse_target_size(request: &str) -> Option<usize> {
+    parse_param(request, PADDING_PARAM)?.parse().ok()
+}
+
+/// Parses the target sizes of the objects a document depends on from its
+/// HTTP request.
+///
+/// # Returns
+/// The comma-separated values of the `alpaca-refs` GET parameter, or None if
+/// the request carries no such (valid) parameter. The values are in the
+/// order of the nodes of the document's `PageGraph`, after the document
+/// itself.
+pub fn parse_dependency_sizes(request: &str) -> Option<Vec<usize>> {
+    parse_param(request, REFS_PARAM)?
+        .split(',')
+        .filter(|size| !size.is_empty())
+        .map(|size| size.parse().ok())
+        .collect()
+}
+
+/// Parses the padding objects of the documents a document depends on from
+/// its HTTP request.
+///
+/// # Returns
+/// The comma-separated values of the `alpaca-pads` GET parameter, as
+/// (node, target size) pairs written "node:size", or None if the request
+/// carries no such (valid) parameter. Nodes are numbered as in the
+/// document's `PageGraph`.
+pub fn parse_padding_sizes(request: &str) -> Option<Vec<(usize, usize)>> {
+    parse_param(request, PADS_PARAM)?
+        .split(',')
+        .filter(|pad| !pad.is_empty())
+        .map(|pad| {
+            let (node, size) = pad.split_once(':')?;
+            Some((node.parse().ok()?, size.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Returns the value of GET parameter `name` in an HTTP request.
+pub(crate) fn parse_param<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    let uri = parse_request_uri(request);
+    let query = uri.split('#').next()?.split_once('?')?.1;
+    query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .find(|&(param, _)| param == name)
+        .map(|(_, value)| value)
+}
+
+/// Parses the request target (URI) from an HTTP request.
+///
+/// `request` is either an HTTP request (e.g., "GET /img.png HTTP/1.1",
+/// possibly followed by headers) or the request target itself.
+pub fn parse_request_uri(request: &str) -> &str {
+    let line = request.lines().next().unwrap_or("");
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some(_), Some(uri)) => uri,
+        (Some(uri), None) => uri,
+        _ => "",
+    }
+}
+
+/// Resolves a URI referenced by a document into a path on the server.
+///
+/// # Arguments
+///
+/// `base` - URI of the referencing document.
+/// `reference` - URI, as written in the document.
+///
+/// # Returns
+/// The path (and query, if any) of the referenced object, or None if the
+/// reference does not point to an object on the same server.
+pub fn resolve_uri(base: &str, reference: &str) -> Option<String> {
+    let reference = reference.trim();
+    let reference = reference.split('#').next().unwrap_or("");
+    if reference.is_empty() || reference.starts_with("//") || has_scheme(reference) {
+        return None;
+    }
+
+    let mut parts = reference.splitn(2, '?');
+    let path = parts.next().unwrap_or("");
+    let query = parts.next();
+
+    let joined = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        let base = base.split(['?', '#']).next().unwrap_or("");
+        let base_dir = &base[..base.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        format!("/{}{}", base_dir.trim_start_matches('/'), path)
+    };
+
+    // Remove dot segments.
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in joined.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+    let mut resolved = format!("/{}", segments.join("/"));
+    let last = joined.rsplit('/').next().unwrap_or("");
+    if !segments.is_empty() && (last.is_empty() || last == "." || last == "..") {
+        resolved.push('/');
+    }
+    if let Some(query) = query {
+        resolved.push('?');
+        resolved.push_str(query);
+    }
+
+    Some(resolved)
+}
+
+/// Parses the objects contained in an HTML page or CSS stylesheet.
+///
+/// In HTML pages, objects are referenced by element attributes (e.g.,
+/// `<img src>`), and by `url()` and `@import` in `<style>` elements and
+/// `style` attributes. In CSS, they are referenced by `url()` and `@import`.
+///
+/// The returned objects have their URI (as written) and its position in
+/// the document set, but no content and size.
+pub fn parse_objects(document: &Object) -> Vec<Object> {
+    let mut refs = Vec::new();
+    match document.kind {
+        ObjectKind::HTML => parse_html_refs(&document.content, &mut refs),
+        ObjectKind::CSS => parse_css_refs(&document.content, 0, document.content.len(), &mut refs),
+        _ => {}
+    }
+
+    refs.into_iter()
+        .filter_map(|(position, len, kind, candidate_set)| {
+            let uri =
+                String::from_utf8(document.content[position..position + len].to_vec()).ok()?;
+            if uri.trim().is_empty() || uri.starts_with('#') || has_scheme(&uri) {
+                return None;
+            }
+            // Images may be SVG documents, which are padded differently.
+            let kind = match kind {
+                Some(ObjectKind::IMG) if parse_kind_from_uri(&uri) == ObjectKind::SVG => {
+                    ObjectKind::SVG
+                }
+                Some(kind) => kind,
+                None => parse_kind_from_uri(&uri),
+            };
+            Some(Object {
+                kind,
+                content: Vec::new(),
+                size: 0,
+                uri: Some(uri),
+                position: Some(position),
+                target_size: None,
+                candidate_set,
+            })
+        })
+        .collect()
+}
+
+/// A reference to an object in a document: position and length of its URI,
+/// its kind if known from the context, and its candidate set if any.
+type Reference = (usize, usize, Option<ObjectKind>, Option<usize>);
+
+fn parse_html_refs(html: &[u8], refs: &mut Vec<Reference>) {
+    // Candidate set of the `picture` element being parsed, if any, and the
+    // number of candidate sets so far.
+    let mut picture = None;
+    let mut candidate_sets = 0;
+
+    let mut i = 0;
+    while let Some(lt) = find(html, i, b"<") {
+        i = lt + 1;
+        if html[i..].starts_with(b"!--") {
+            i = find(html, i + 3, b"-->").map_or(html.len(), |end| end + 3);
+            continue;
+        }
+        let name_end = i + html[i..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .count();
+        if name_end == i {
+            // Closing tag, doctype, processing instruction or stray '<'.
+            if starts_with_ignore_case(&html[i..], b"/picture") {
+                picture = None;
+            }
+            i = find(html, i, b">").map_or(html.len(), |end| end + 1);
+            continue;
+        }
+        let tag = String::from_utf8_lossy(&html[i..name_end]).to_ascii_lowercase();
+        let (attrs, tag_end) = parse_html_attrs(html, name_end);
+        i = tag_end;
+
+        // The browser fetches a single image among the sources of a
+        // `picture`, or among an image's `src` and `srcset`.
+        let has_srcset = attrs.iter().any(|a| a.0 == "srcset");
+        let candidate_set = match tag.as_str() {
+            "picture" => {
+                picture = Some(candidate_sets);
+                candidate_sets += 1;
+                None
+            }
+            "img" | "source" if picture.is_some() => picture,
+            "img" if has_srcset => {
+                candidate_sets += 1;
+                Some(candidate_sets - 1)
+            }
+            _ => None,
+        };
+
+        for &(ref name, start, end) in &attrs {
+            if name == "style" {
+                parse_css_refs(html, start, end, refs);
+            } else if name == "srcset" && (tag == "img" || tag == "source") {
+                parse_srcset(html, start, end, candidate_set, refs);
+            } else if is_html_ref_attr(&tag, name, &attrs, html) {
+                let kind = match tag.as_str() {
+                    "img" => Some(ObjectKind::IMG),
+                    // Nested documents.
+                    "iframe" | "frame" => Some(ObjectKind::HTML),
+                    "link" if attr_has_word(html, &attrs, "rel", "stylesheet") => {
+                        Some(ObjectKind::CSS)
+                    }
+                    _ => None,
+                };
+                refs.push((start, end - start, kind, candidate_set));
+            }
+        }
+
+        if tag == "style" {
+            let end = find_closing_tag(html, i, "style");
+            parse_css_refs(html, i, end, refs);
+            i = end;
+        } else if HTML_RAW_TEXT_TAGS.contains(&tag.as_str()) {
+            i = find_closing_tag(html, i, &tag);
+        }
+    }
+}
+
+/// Parses the attributes of an HTML tag starting at `i`, right after its
+/// name.
+///
+/// # Returns
+/// The attributes as (lowercase name, value start, value end), and the
+/// position right after the tag.
+fn parse_html_attrs(html: &[u8], mut i: usize) -> (Vec<(String, usize, usize)>, usize) {
+    let mut attrs = Vec::new();
+    loop {
+        while i < html.len() && (html[i].is_ascii_whitespace() || html[i] == b'/') {
+            i += 1;
+        }
+        if i >= html.len() || html[i] == b'>' {
+            return (attrs, (i + 1).min(html.len()));
+        }
+        let name_start = i;
+        while i < html.len() && !b" \t\r\n/>=".contains(&html[i]) {
+            i += 1;
+        }
+        let name = String::from_utf8_lossy(&html[name_start..i]).to_ascii_lowercase();
+        while i < html.len() && html[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= html.len() || html[i] != b'=' {
+            attrs.push((name, i, i));
+            continue;
+        }
+        i += 1;
+        while i < html.len() && html[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let (start, end) = match html.get(i) {
+            Some(&quote) if quote == b'"' || quote == b'\'' => {
+                let end = find(html, i + 1, &[quote]).unwrap_or(html.len());
+                let value = (i + 1, end);
+                i = (end + 1).min(html.len());
+                value
+            }
+            _ => {
+                let start = i;
+                while i < html.len() && !html[i].is_ascii_whitespace() && html[i] != b'>' {
+                    i += 1;
+                }
+                (start, i)
+            }
+        };
+        attrs.push((name, start, end));
+    }
+}
+
+fn is_html_ref_attr(tag: &str, attr: &str, attrs: &[(String, usize, usize)], html: &[u8]) -> bool {
+    if tag == "link" {
+        return attr == "href"
+            && HTML_LINK_RELS
+                .iter()
+                .any(|rel| attr_has_word(html, attrs, "rel", rel));
+    }
+    HTML_REF_ATTRS.iter().any(|&(t, a)| t == tag && a == attr)
 }
 
-/// Parses the objects contained in an HTML page.
-#[allow(unused)]
-pub fn parse_objects(html: &Object) -> Vec<Object> {
-    unimplemented!();
+/// Returns whether attribute `name` is a whitespace-separated list of words
+/// containing `word` (ASCII case-insensitive).
+fn attr_has_word(html: &[u8], attrs: &[(String, usize, usize)], name: &str, word: &str) -> bool {
+    attrs.iter().any(|&(ref n, start, end)| {
+        n == name
+            && html[start..end]
+                .split(|c| c.is_ascii_whitespace())
+                .any(|w| w.eq_ignore_ascii_case(word.as_bytes()))
+    })
+}
+
+/// Returns the position of the closing tag `</tag` after `i`, or the end of
+/// the document.
+fn find_closing_tag(html: &[u8], mut i: usize, tag: &str) -> usize {
+    let closing = format!("</{}", tag);
+    while let Some(lt) = find(html, i, b"</") {
+        if starts_with_ignore_case(&html[lt..], closing.as_bytes()) {
+            return lt;
+        }
+        i = lt + 2;
+    }
+    html.len()
+}
+
+/// Parses the references in the CSS between positions `start` and `end` of
+/// `css`.
+fn parse_css_refs(css: &[u8], start: usize, end: usize, refs: &mut Vec<Reference>) {
+    let mut i = start;
+    while i < end {
+        let rest = &css[i..end];
+        if rest.starts_with(b"/*") {
+            i = find(&css[..end], i + 2, b"*/").map_or(end, |e| e + 2);
+        } else if starts_with_ignore_case(rest, b"url(") {
+            i = parse_css_url(css, i + 4, end, None, refs);
+        } else if starts_with_ignore_case(rest, b"@import") {
+            i = skip_whitespace(css, i + 7, end);
+            if starts_with_ignore_case(&css[i..end], b"url(") {
+                i = parse_css_url(css, i + 4, end, Some(ObjectKind::CSS), refs);
+            } else if i < end && (css[i] == b'"' || css[i] == b'\'') {
+                let quote_end = find(&css[..end], i + 1, &[css[i]]).unwrap_or(end);
+                refs.push((i + 1, quote_end - i - 1, Some(ObjectKind::CSS), None));
+                i = quote_end + 1;
+            }
+        } else if rest[0] == b'"' || rest[0] == b'\'' {
+            // Skip strings, which may contain "url(".
+            i = find(&css[..end], i + 1, &[rest[0]]).map_or(end, |e| e + 1);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Parses the URI of a `url()` starting at `i`, right after "url(".
+///
+/// # Returns
+/// The position after the closing parenthesis.
+fn parse_css_url(
+    css: &[u8],
+    i: usize,
+    end: usize,
+    kind: Option<ObjectKind>,
+    refs: &mut Vec<Reference>,
+) -> usize {
+    let i = skip_whitespace(css, i, end);
+    if i >= end {
+        return end;
+    }
+    let (start, uri_end) = if css[i] == b'"' || css[i] == b'\'' {
+        (i + 1, find(&css[..end], i + 1, &[css[i]]).unwrap_or(end))
+    } else {
+        let len = css[i..end]
+            .iter()
+            .take_while(|&&c| c != b')' && !c.is_ascii_whitespace())
+            .count();
+        (i, i + len)
+    };
+    refs.push((start, uri_end - start, kind, None));
+    find(&css[..end], uri_end, b")").map_or(end, |e| e + 1)
+}
+
+/// Parses the image candidates of an `srcset` attribute, between positions
+/// `start` and `end` of `html`.
+///
+/// Candidates are separated by commas, and are a URL followed by optional
+/// descriptors (e.g., "img-2x.png 2x, img-640.png 640w").
+fn parse_srcset(
+    html: &[u8],
+    start: usize,
+    end: usize,
+    candidate_set: Option<usize>,
+    refs: &mut Vec<Reference>,
+) {
+    let mut i = start;
+    loop {
+        while i < end && (html[i].is_ascii_whitespace() || html[i] == b',') {
+            i += 1;
+        }
+        if i >= end {
+            return;
+        }
+
+        // URLs may contain commas, but not end with one.
+        let uri_start = i;
+        while i < end && !html[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let mut uri_end = i;
+        while uri_end > uri_start && html[uri_end - 1] == b',' {
+            uri_end -= 1;
+        }
+        refs.push((
+            uri_start,
+            uri_end - uri_start,
+            Some(ObjectKind::IMG),
+            candidate_set,
+        ));
+
+        // Skip the descriptors, up to the next candidate.
+        if uri_end == i {
+            let mut depth = 0;
+            while i < end && (depth > 0 || html[i] != b',') {
+                match html[i] {
+                    b'(' => depth += 1,
+                    b')' if depth > 0 => depth -= 1,
+                    _ => {}
+                }
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Parses the object's kind from the extension of its URI.
+fn parse_kind_from_uri(uri: &str) -> ObjectKind {
+    let path = uri.split(['?', '#']).next().unwrap_or("");
+    if path == ALPACA_PAD_PATH {
+        return ObjectKind::Alpaca;
+    }
+    let file = path.rsplit('/').next().unwrap_or("");
+    let extension = match file.rfind('.') {
+        Some(i) => file[i + 1..].to_ascii_lowercase(),
+        None => return ObjectKind::Unknown,
+    };
+    match extension.as_str() {
+        "html" | "htm" => ObjectKind::HTML,
+        "css" => ObjectKind::CSS,
+        "png" | "jpg" | "jpeg" | "gif" | "webp" | "ico" | "bmp" | "avif" => ObjectKind::IMG,
+        "svg" => ObjectKind::SVG,
+        "js" | "mjs" => ObjectKind::JS,
+        "woff" | "woff2" | "ttf" | "otf" | "eot" => ObjectKind::Font,
+        "json" => ObjectKind::JSON,
+        "txt" => ObjectKind::Text,
+        "mp4" | "m4v" | "m4a" | "mov" => ObjectKind::MP4,
+        "webm" | "mkv" | "mka" => ObjectKind::WebM,
+        _ => ObjectKind::Unknown,
+    }
+}
+
+/// Parses the object's kind from its leading bytes.
+fn sniff_object_kind(raw: &[u8]) -> ObjectKind {
+    let text = &raw[skip_whitespace(raw, 0, raw.len())..];
+    if raw.starts_with(b"\x89PNG")
+        || raw.starts_with(b"\xff\xd8\xff")
+        || raw.starts_with(b"GIF8")
+        || raw.starts_with(b"RIFF") && raw.get(8..12) == Some(b"WEBP")
+    {
+        ObjectKind::IMG
+    } else if raw.starts_with(b"wOFF") || raw.starts_with(b"wOF2") {
+        ObjectKind::Font
+    } else if raw.get(4..8) == Some(b"ftyp") {
+        ObjectKind::MP4
+    } else if raw.starts_with(b"\x1a\x45\xdf\xa3") {
+        ObjectKind::WebM
+    } else if starts_with_ignore_case(text, b"<!doctype html")
+        || starts_with_ignore_case(text, b"<html")
+    {
+        ObjectKind::HTML
+    } else if starts_with_ignore_case(text, b"<svg") {
+        ObjectKind::SVG
+    } else {
+        ObjectKind::Unknown
+    }
+}
+
+/// Returns whether `uri` starts with a scheme (e.g., "https:", "data:").
+fn has_scheme(uri: &str) -> bool {
+    match uri.find(':') {
+        Some(i) => {
+            i > 0
+                && uri[..i]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        None => false,
+    }
+}
+
+fn find(haystack: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    if from > haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+fn starts_with_ignore_case(haystack: &[u8], prefix: &[u8]) -> bool {
+    haystack.len() >= prefix.len() && haystack[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn skip_whitespace(text: &[u8], mut i: usize, end: usize) -> usize {
+    while i < end && text[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(kind: ObjectKind, content: &str) -> Object {
+        Object {
+            kind,
+            content: content.as_bytes().to_vec(),
+            size: content.len(),
+            uri: Some("/index.html".to_string()),
+            position: None,
+            target_size: None,
+            candidate_set: None,
+        }
+    }
+
+    fn uris(objects: &[Object]) -> Vec<&str> {
+        objects
+            .iter()
+            .map(|o| o.uri.as_ref().unwrap().as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_object_kind() {
+        assert_eq!(parse_object_kind(b"", "/a/b.CSS?v=2"), ObjectKind::CSS);
+        assert_eq!(
+            parse_object_kind(b"", "GET /img.png HTTP/1.1"),
+            ObjectKind::IMG
+        );
+        assert_eq!(
+            parse_object_kind(b"", "/alpaca.pad?alpaca-padding=3"),
+            ObjectKind::Alpaca
+        );
+        assert_eq!(parse_object_kind(b"", "/data.json"), ObjectKind::JSON);
+        assert_eq!(parse_object_kind(b"", "/video.webm"), ObjectKind::WebM);
+        // Unknown extensions fall back to the object's content.
+        assert_eq!(parse_object_kind(b"\x89PNG\r\n", "/image"), ObjectKind::IMG);
+        assert_eq!(
+            parse_object_kind(b"\0\0\0\x18ftypmp42", "/v"),
+            ObjectKind::MP4
+        );
+        assert_eq!(
+            parse_object_kind(b"\n<!DOCTYPE html>", "/"),
+            ObjectKind::HTML
+        );
+        assert_eq!(parse_object_kind(b"", "/logo.svg"), ObjectKind::SVG);
+        assert_eq!(parse_object_kind(b"<svg xmlns=", "/logo"), ObjectKind::SVG);
+        assert_eq!(parse_object_kind(b"???", "/script.js"), ObjectKind::JS);
+        assert_eq!(parse_object_kind(b"", "/f.WOFF2"), ObjectKind::Font);
+        assert_eq!(parse_object_kind(b"wOF2\0\x01", "/font"), ObjectKind::Font);
+        assert_eq!(parse_object_kind(b"???", "/script.py"), ObjectKind::Unknown);
+    }
+
+    #[test]
+    fn test_parse_target_size() {
+        assert_eq!(parse_target_size("/img.png?alpaca-padding=300"), Some(300));
+        assert_eq!(
+            parse_target_size("GET /img.png?v=1&alpaca-padding=42#x HTTP/1.1\r\nHost: a\r\n"),
+            Some(42)
+        );
+        assert_eq!(parse_target_size("/img.png"), None);
+        assert_eq!(parse_target_size("/img.png?alpaca-padding=-1"), None);
+        assert_eq!(parse_target_size("/img.png?xalpaca-padding=1"), None);
+    }
+
+    #[test]
+    fn test_parse_dependency_sizes() {
+        assert_eq!(
+            parse_dependency_sizes("/a.css?alpaca-padding=900&alpaca-refs=300,1200"),
+            Some(vec![300, 1200])
+        );
+        assert_eq!(parse_dependency_sizes("/a.css?alpaca-refs="), Some(vec![]));
+        assert_eq!(parse_dependency_sizes("/a.css?alpaca-padding=900"), None);
+        assert_eq!(parse_dependency_sizes("/a.css?alpaca-refs=300,x"), None);
+    }
+
+    #[test]
+    fn test_parse_padding_sizes() {
+        assert_eq!(
+            parse_padding_sizes("GET /a.css?alpaca-pads=0:300,2:1200 HTTP/1.1"),
+            Some(vec![(0, 300), (2, 1200)])
+        );
+        assert_eq!(parse_padding_sizes("/a.css?alpaca-refs=300"), None);
+        assert_eq!(parse_padding_sizes("/a.css?alpaca-pads=300"), None);
+    }
+
+    #[test]
+    fn test_parse_request_uri() {
+        assert_eq!(parse_request_uri("GET /a?b HTTP/1.1\r\nHost: x"), "/a?b");
+        assert_eq!(parse_request_uri("/a?b"), "/a?b");
+        assert_eq!(parse_request_uri(""), "");
+    }
+
+    #[test]
+    fn test_resolve_uri() {
+        assert_eq!(
+            resolve_uri("/index.html", "img.png"),
+            Some("/img.png".to_string())
+        );
+        assert_eq!(
+            resolve_uri("/a/b/page.html?x=1", "../c/./d.png?v=2#top"),
+            Some("/a/c/d.png?v=2".to_string())
+        );
+        assert_eq!(
+            resolve_uri("/a/page.html", "/abs.css"),
+            Some("/abs.css".to_string())
+        );
+        assert_eq!(
+            resolve_uri("/a/", "../../../x.png"),
+            Some("/x.png".to_string())
+        );
+        assert_eq!(resolve_uri("/a/", "sub/"), Some("/a/sub/".to_string()));
+        // Objects on other servers, or embedded in the document.
+        assert_eq!(resolve_uri("/", "https://example.com/x.png"), None);
+        assert_eq!(resolve_uri("/", "//example.com/x.png"), None);
+        assert_eq!(resolve_uri("/", "data:image/png;base64,AAAA"), None);
+        assert_eq!(resolve_uri("/", "#section"), None);
+    }
+
+    #[test]
+    fn test_parse_objects_html() {
+        let html = "<!DOCTYPE html><html><head>\
+                    <link rel=\"stylesheet\" href=\"style.css\">\
+                    <link rel=\"canonical\" href=\"/index.html\">\
+                    <style>body { background: url('bg.png'); }</style>\
+                    <script src=\"/app.js\">var s = '<img src=\"no.png\">';</script>\
+                    </head><body>\
+                    <!-- <img src=\"commented.png\"> -->\
+                    <IMG SRC=/a.png ALT=x>\
+                    <div style=\"background-image: url(div.jpg)\"></div>\
+                    <img src=\"https://example.com/ext.png\">\
+                    <img src=\"logo.svg\">\
+                    <img src='data:image/png;base64,AAAA'>\
+                    </body></html>";
+        let objects = parse_objects(&document(ObjectKind::HTML, html));
+        assert_eq!(
+            uris(&objects),
+            vec![
+                "style.css",
+                "bg.png",
+                "/app.js",
+                "/a.png",
+                "div.jpg",
+                "logo.svg"
+            ]
+        );
+        let kinds = objects.iter().map(|o| o.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ObjectKind::CSS,
+                ObjectKind::IMG,
+                ObjectKind::JS,
+                ObjectKind::IMG,
+                ObjectKind::IMG,
+                ObjectKind::SVG,
+            ]
+        );
+        // Positions point at the URIs in the document.
+        for o in &objects {
+            let uri = o.uri.as_ref().unwrap();
+            let position = o.position.unwrap();
+            assert_eq!(&html[position..position + uri.len()], uri);
+        }
+    }
+
+    #[test]
+    fn test_parse_objects_iframe() {
+        let html = "<iframe src=\"/widget?id=1\" title=w></iframe>\
+                    <iframe src=\"https://example.com/embed\"></iframe>\
+                    <iframe srcdoc=\"<img src=x.png>\"></iframe>\
+                    <frameset><frame src=menu.php></frameset>";
+        let objects = parse_objects(&document(ObjectKind::HTML, html));
+        assert_eq!(uris(&objects), vec!["/widget?id=1", "menu.php"]);
+        assert!(objects.iter().all(|o| o.kind == ObjectKind::HTML));
+    }
+
+    #[test]
+    fn test_parse_objects_srcset() {
+        let html = "<img src=a.png srcset=\"a-2x.png 2x, a,3x.png 3x,a-4x.png\">\
+                    <img src=b.png>\
+                    <picture>\
+                    <source srcset=\"c.webp, c-2x.webp 2x\" type=\"image/webp\">\
+                    <source media=\"(min-width: 600px)\" srcset=c-w.png>\
+                    <img src=c.png alt=c>\
+                    </PICTURE>\
+                    <img srcset=\"d.png 480w,d-l.png 1000w\" sizes=\"(max-width: 600px) 480px\">";
+        let objects = parse_objects(&document(ObjectKind::HTML, html));
+        assert_eq!(
+            uris(&objects),
+            vec![
+                "a.png",
+                "a-2x.png",
+                "a,3x.png",
+                "a-4x.png",
+                "b.png",
+                "c.webp",
+                "c-2x.webp",
+                "c-w.png",
+                "c.png",
+                "d.png",
+                "d-l.png",
+            ]
+        );
+        let sets = objects.iter().map(|o| o.candidate_set).collect::<Vec<_>>();
+        assert_eq!(
+            sets,
+            vec![
+                Some(0),
+                Some(0),
+                Some(0),
+                Some(0),
+                None,
+                Some(1),
+                Some(1),
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(2),
+            ]
+        );
+        assert!(objects.iter().all(|o| o.kind == ObjectKind::IMG));
+        for o in &objects {
+            let uri = o.uri.as_ref().unwrap();
+            let position = o.position.unwrap();
+            assert_eq!(&html[position..position + uri.len()], uri);
+        }
+    }
+
+    #[test]
+    fn test_parse_objects_css() {
+        let css = "@import \"base.css\";\n\
+                   @import url( 'print.css' ) print;\n\
+                   /* url(commented.png) */\n\
+                   @font-face { src: url(\"/fonts/a.woff2\") format(\"woff2\"); }\n\
+                   p::before { content: \"url(string.png)\"; }\n\
+                   div { background: URL(img/bg.gif) no-repeat; }\n";
+        let objects = parse_objects(&document(ObjectKind::CSS, css));
+        assert_eq!(
+            uris(&objects),
+            vec!["base.css", "print.css", "/fonts/a.woff2", "img/bg.gif"]
+        );
+        assert_eq!(objects[0].kind, ObjectKind::CSS);
+        assert_eq!(objects[1].kind, ObjectKind::CSS);
+        assert_eq!(objects[3].kind, ObjectKind::IMG);
+        for o in &objects {
+            let uri = o.uri.as_ref().unwrap();
+            let position = o.position.unwrap();
+            assert_eq!(&css[position..position + uri.len()], uri);
+        }
+    }
+
+    #[test]
+    fn test_parse_objects_other() {
+        let objects = parse_objects(&document(ObjectKind::IMG, "url(a.png)"));
+        assert!(objects.is_empty());
+    }
 }
@@ -0,0 +1,277 @@
+//! Decides where references to new (padding) objects are inserted in
+//! documents.
+//!
+//! Browsers request objects in the order they find their references, so
+//! where padding objects are referenced shows in the order of requests.
+use rand::Rng;
+
+use objects::*;
+use parsing::parse_objects;
+
+// Elements whose children are alternatives to one another (e.g., the
+// sources of a `picture`), among which no reference may be inserted.
+static HTML_ALTERNATIVES_TAGS: &[&str] = &["picture", "video", "audio", "object"];
+
+/// Where references to padding objects are inserted in HTML pages.
+///
+/// Stylesheets always import padding objects at their start, since imports
+/// must precede any other rule.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Placement {
+    /// Before a reference of the page chosen uniformly at random, or at the
+    /// end of the body.
+    Random,
+    /// At the end of the body.
+    #[default]
+    BodyEnd,
+    /// Before a reference to an image of the page chosen at random, so that
+    /// padding images are interleaved with the page's own.
+    Interleaved,
+    /// As preload hints at the end of the head.
+    Preload,
+}
+
+/// Returns the position at which to insert each of `count` references to
+/// new objects in the document.
+///
+/// # Returns
+/// The positions, or Err(()) if the document cannot reference new objects.
+pub(crate) fn get_padding_positions<R: Rng>(
+    rng: &mut R,
+    document: &Object,
+    placement: Placement,
+    count: usize,
+) -> Result<Vec<usize>, ()> {
+    let html = &document.content;
+    let position = match document.kind {
+        ObjectKind::HTML => match placement {
+            Placement::Random | Placement::Interleaved => {
+                let mut slots = get_reference_slots(document, placement);
+                slots.push(find_body_end(html));
+                return Ok((0..count).map(|_| *rng.choose(&slots).unwrap()).collect());
+            }
+            Placement::BodyEnd => find_body_end(html),
+            Placement::Preload => find_head_end(html),
+        },
+        ObjectKind::CSS => find_css_rules_start(html),
+        _ => return Err(()),
+    };
+    Ok(vec![position; count])
+}
+
+/// Returns a reference to the object at `uri` which can be inserted in a
+/// document of the given kind, or None if the document cannot reference new
+/// objects.
+///
+/// References at the end of the body are the longest.
+pub(crate) fn get_padding_ref(kind: ObjectKind, uri: &str, placement: Placement) -> Option<String> {
+    match kind {
+        ObjectKind::HTML if placement == Placement::Preload => Some(format!(
+            "<link rel=\"preload\" href=\"{}\" as=\"image\">",
+            uri
+        )),
+        ObjectKind::HTML => Some(format!(
+            "<img src=\"{}\" alt=\"\" style=\"display:none\">",
+            uri
+        )),
+        ObjectKind::CSS => Some(format!("@import url(\"{}\");", uri)),
+        _ => None,
+    }
+}
+
+/// Returns the positions of the tags referencing objects in the body of an
+/// HTML page (only images for `Placement::Interleaved`), before which an
+/// image can be inserted.
+fn get_reference_slots(document: &Object, placement: Placement) -> Vec<usize> {
+    let html = &document.content;
+    let body = (find_body_start(html), find_body_end(html));
+    let mut slots = parse_objects(document)
+        .into_iter()
+        .filter(|o| {
+            placement != Placement::Interleaved
+                || matches!(o.kind, ObjectKind::IMG | ObjectKind::SVG)
+        })
+        .filter_map(|o| find_tag_start(html, o.position?))
+        .filter(|&p| p >= body.0 && p <= body.1)
+        .filter(|&p| !HTML_ALTERNATIVES_TAGS.iter().any(|t| is_inside(html, p, t)))
+        .collect::<Vec<_>>();
+    slots.dedup();
+    slots
+}
+
+/// Returns the start of the tag whose attribute is at `position`, or None
+/// if `position` is not in a tag (e.g., it is in a `<style>` element).
+fn find_tag_start(html: &[u8], position: usize) -> Option<usize> {
+    // Skip any '<' in the values of the tag's attributes.
+    let lt = (0..position)
+        .rev()
+        .find(|&i| html[i] == b'<' && html[i + 1].is_ascii_alphabetic())?;
+    let mut quote = None;
+    for &c in &html[lt..position] {
+        match (quote, c) {
+            (None, b'"') | (None, b'\'') => quote = Some(c),
+            (None, b'>') => return None,
+            (Some(q), _) if c == q => quote = None,
+            _ => {}
+        }
+    }
+    Some(lt)
+}
+
+/// Returns whether `position` is inside an element `tag` of an HTML page.
+fn is_inside(html: &[u8], position: usize, tag: &str) -> bool {
+    let open = rfind_tag(html, position, &format!("<{}", tag));
+    let close = rfind_tag(html, position, &format!("</{}", tag));
+    match (open, close) {
+        (Some(open), Some(close)) => open > close,
+        (open, _) => open.is_some(),
+    }
+}
+
+/// Returns the last position before `end` of an HTML tag starting with
+/// `prefix` (e.g., "<body" or "</body"), ignoring case.
+fn rfind_tag(html: &[u8], end: usize, prefix: &str) -> Option<usize> {
+    let prefix = prefix.as_bytes();
+    (0..end).rev().find(|&i| {
+        html[i..].len() > prefix.len()
+            && html[i..i + prefix.len()].eq_ignore_ascii_case(prefix)
+            && !html[i + prefix.len()].is_ascii_alphanumeric()
+    })
+}
+
+/// Returns the position right after the end of the tag starting at `start`.
+fn find_tag_end(html: &[u8], start: usize) -> usize {
+    html[start..]
+        .iter()
+        .position(|&c| c == b'>')
+        .map_or(html.len(), |end| start + end + 1)
+}
+
+/// Returns the position where the body of an HTML page starts, after its
+/// `<body>` tag or, if there is none, after its head.
+fn find_body_start(html: &[u8]) -> usize {
+    if let Some(start) = rfind_tag(html, html.len(), "<body") {
+        return find_tag_end(html, start);
+    }
+    rfind_tag(html, html.len(), "</head").map_or(0, |end| find_tag_end(html, end))
+}
+
+/// Returns the position of the closing `</body>` tag of an HTML page, or its
+/// end if there is none.
+fn find_body_end(html: &[u8]) -> usize {
+    (0..html.len())
+        .rev()
+        .find(|&i| html[i..].len() >= 6 && html[i..i + 6].eq_ignore_ascii_case(b"</body"))
+        .unwrap_or(html.len())
+}
+
+/// Returns the position of the closing `</head>` tag of an HTML page or, if
+/// there is none, that of its body (after its doctype if any).
+fn find_head_end(html: &[u8]) -> usize {
+    if let Some(end) = rfind_tag(html, html.len(), "</head") {
+        return end;
+    }
+    if let Some(start) = rfind_tag(html, html.len(), "<body") {
+        return start;
+    }
+    let doctype = html.len() >= 9 && html[..9].eq_ignore_ascii_case(b"<!doctype");
+    if doctype {
+        find_tag_end(html, 0)
+    } else {
+        0
+    }
+}
+
+/// Returns the position where the rules of a CSS stylesheet start, after
+/// its `@charset` rule if any. Imports must precede any other rule.
+fn find_css_rules_start(css: &[u8]) -> usize {
+    if !css.starts_with(b"@charset") {
+        return 0;
+    }
+    css.iter()
+        .position(|&c| c == b';')
+        .map_or(css.len(), |end| end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+    use std::collections::HashSet;
+
+    static HTML: &str = "<html><head><link rel=stylesheet href=s.css></head>\
+                         <body><p style=\"background: url('p.png')\">x</p><img src=a.png>\
+                         <picture><source srcset=b.webp><img src=b.png></picture>\
+                         <video src=v.mp4></video><img alt='<' src=c.png></body></html>";
+
+    fn init_seeded_rng() -> XorShiftRng {
+        XorShiftRng::from_seed([0, 1, 2, 3])
+    }
+
+    fn positions(placement: Placement) -> HashSet<usize> {
+        let document = Object::from(HTML.as_bytes(), "/index.html");
+        get_padding_positions(&mut init_seeded_rng(), &document, placement, 100)
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_get_padding_positions() {
+        let tag = |tag: &str| HTML.find(tag).unwrap();
+        let body_end = tag("</body>");
+
+        let expected = vec![
+            tag("<p "),
+            tag("<img src=a"),
+            tag("<video"),
+            tag("<img alt"),
+            body_end,
+        ];
+        assert_eq!(positions(Placement::Random), expected.into_iter().collect());
+        // Images include those of style attributes.
+        let expected = vec![tag("<p "), tag("<img src=a"), tag("<img alt"), body_end];
+        assert_eq!(
+            positions(Placement::Interleaved),
+            expected.into_iter().collect()
+        );
+        assert_eq!(
+            positions(Placement::BodyEnd),
+            vec![body_end].into_iter().collect()
+        );
+        assert_eq!(
+            positions(Placement::Preload),
+            vec![tag("</head>")].into_iter().collect()
+        );
+
+        let mut rng = init_seeded_rng();
+        let css = Object::from(b"@charset \"utf-8\";p {}", "/style.css");
+        assert_eq!(
+            get_padding_positions(&mut rng, &css, Placement::Random, 2),
+            Ok(vec![17, 17])
+        );
+        let json = Object::from(b"{}", "/data.json");
+        assert!(get_padding_positions(&mut rng, &json, Placement::BodyEnd, 1).is_err());
+    }
+
+    #[test]
+    fn test_find_head_end() {
+        assert_eq!(find_head_end(b"<!doctype html><p>"), 15);
+        assert_eq!(find_head_end(b"<html><BODY><p></BODY>"), 6);
+        assert_eq!(find_head_end(b"<p>"), 0);
+        assert_eq!(find_body_start(b"<head></head><p>"), 13);
+    }
+
+    #[test]
+    fn test_get_padding_ref() {
+        // References at the end of the body bound the growth of pages.
+        let body_end = get_padding_ref(ObjectKind::HTML, "/x.png", Placement::BodyEnd).unwrap();
+        let preload = get_padding_ref(ObjectKind::HTML, "/x.png", Placement::Preload).unwrap();
+        assert!(preload.len() <= body_end.len());
+        assert_eq!(
+            preload,
+            "<link rel=\"preload\" href=\"/x.png\" as=\"image\">"
+        );
+        assert!(get_padding_ref(ObjectKind::JSON, "/x.png", Placement::BodyEnd).is_none());
+    }
+}
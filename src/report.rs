@@ -0,0 +1,111 @@
+//! Reports what morphing a page costs.
+//!
+//! Morphing pads the page and the objects it fetches, and adds padding
+//! objects: the report tells how many bytes loading the page takes before
+//! and after.
+use graph::PageGraph;
+use objects::ObjectKind;
+
+/// What morphing a page did to the bytes of loading it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MorphReport {
+    /// Size of the HTML page before morphing.
+    pub html_size: usize,
+    /// Size the HTML page is padded to.
+    pub html_target_size: usize,
+    /// Sizes of the objects fetched when loading the page, before morphing.
+    /// A set of candidates among which the browser fetches one (e.g., the
+    /// candidates of an image's `srcset`) counts as its largest.
+    pub object_sizes: Vec<usize>,
+    /// Sizes the objects are padded to, in the order of `object_sizes`.
+    pub object_target_sizes: Vec<usize>,
+    /// Sizes of the padding objects added to the page.
+    pub padding_sizes: Vec<usize>,
+}
+
+impl MorphReport {
+    /// Returns the number of objects fetched when loading the page, before
+    /// morphing.
+    pub fn object_count(&self) -> usize {
+        self.object_sizes.len()
+    }
+
+    /// Returns the number of padding objects added to the page.
+    pub fn padding_count(&self) -> usize {
+        self.padding_sizes.len()
+    }
+
+    /// Returns the bytes of loading the page before morphing.
+    pub fn original_size(&self) -> usize {
+        self.html_size + self.object_sizes.iter().sum::<usize>()
+    }
+
+    /// Returns the bytes of loading the morphed page.
+    pub fn target_size(&self) -> usize {
+        self.html_target_size
+            + self.object_target_sizes.iter().sum::<usize>()
+            + self.padding_sizes.iter().sum::<usize>()
+    }
+
+    /// Returns the bytes morphing adds to loading the page.
+    pub fn added_bytes(&self) -> usize {
+        self.target_size() - self.original_size()
+    }
+
+    /// Returns the bytes morphing adds to loading the page, as a ratio of
+    /// the bytes of loading it before morphing.
+    pub fn overhead_ratio(&self) -> f64 {
+        self.added_bytes() as f64 / self.original_size() as f64
+    }
+}
+
+/// Returns the report of morphing the page of the graph, given the groups
+/// of objects the browser fetches as one (see `get_fetched_objects`), and
+/// the size the page is padded to.
+pub(crate) fn get_report(
+    graph: &PageGraph,
+    fetched: &[Vec<usize>],
+    html_target_size: usize,
+) -> MorphReport {
+    let mut report = MorphReport {
+        html_size: graph.nodes[0].object.size,
+        html_target_size,
+        object_sizes: Vec::new(),
+        object_target_sizes: Vec::new(),
+        padding_sizes: Vec::new(),
+    };
+    for nodes in fetched {
+        let object = &graph.nodes[nodes[0]].object;
+        let target_size = object.target_size.unwrap_or(object.size);
+        if object.kind == ObjectKind::Alpaca {
+            report.padding_sizes.push(target_size);
+            continue;
+        }
+        let size = nodes.iter().map(|&i| graph.nodes[i].object.size).max();
+        report.object_sizes.push(size.unwrap_or(0));
+        report.object_target_sizes.push(target_size);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_morph_report() {
+        let report = MorphReport {
+            html_size: 1000,
+            html_target_size: 1500,
+            object_sizes: vec![2000, 500],
+            object_target_sizes: vec![2500, 1000],
+            padding_sizes: vec![1000],
+        };
+        assert_eq!(report.object_count(), 2);
+        assert_eq!(report.padding_count(), 1);
+        assert_eq!(report.original_size(), 3500);
+        assert_eq!(report.target_size(), 6000);
+        assert_eq!(report.added_bytes(), 2500);
+        assert!((report.overhead_ratio() - 2500.0 / 3500.0).abs() < 1e-12);
+    }
+}
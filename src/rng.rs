@@ -0,0 +1,76 @@
+//! Provides the random number generator used for security-relevant
+//! randomness, i.e., sampling target sizes and generating padding.
+//!
+//! An attacker who could predict the generator's output could predict
+//! the morphed sizes; hence we use a cryptographically secure generator
+//! seeded from the operating system.
+//!
+//! Alternatively, a generator may be seeded with a keyed PRF (HMAC-SHA256)
+//! over a visitor's session and a page, so that the visitor sees the page
+//! morphed the same on every load, while other visitors see it morphed
+//! independently.
+use hmac::{Hmac, Mac};
+use rand::chacha::ChaChaRng;
+use rand::{OsRng, Rng, SeedableRng};
+use sha2::Sha256;
+
+/// Returns a ChaCha20 random number generator seeded from the operating
+/// system's randomness source.
+pub fn secure_rng() -> ChaChaRng {
+    let mut os_rng = OsRng::new().expect("Failed to access OS randomness");
+    os_rng.gen()
+}
+
+/// Returns a ChaCha20 random number generator seeded with HMAC-SHA256,
+/// keyed with `secret`, of the session identifier `session` and the page
+/// URL `url`.
+///
+/// Given the same arguments, it always generates the same numbers; without
+/// `secret`, they are unpredictable.
+pub fn session_rng(secret: &[u8], session: &[u8], url: &str) -> ChaChaRng {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    // Prefix the session with its length, so that no two pairs of session
+    // and URL are hashed alike.
+    mac.update(&(session.len() as u64).to_be_bytes());
+    mac.update(session);
+    mac.update(url.as_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let seed = digest
+        .chunks(4)
+        .map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]]))
+        .collect::<Vec<_>>();
+    ChaChaRng::from_seed(&seed[..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secure_rng_independent() {
+        // Each generator is seeded independently.
+        let a: [u64; 4] = secure_rng().gen();
+        let b: [u64; 4] = secure_rng().gen();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_session_rng() {
+        let a: [u64; 4] = session_rng(b"secret", b"alice", "/index.html").gen();
+        let b: [u64; 4] = session_rng(b"secret", b"alice", "/index.html").gen();
+        assert_eq!(a, b);
+
+        // A different secret, session or page seeds a different generator.
+        let others = [
+            session_rng(b"other", b"alice", "/index.html"),
+            session_rng(b"secret", b"bob", "/index.html"),
+            session_rng(b"secret", b"alice", "/about.html"),
+            session_rng(b"secret", b"alice/", "index.html"),
+        ];
+        for mut rng in others.iter().cloned() {
+            let other: [u64; 4] = rng.gen();
+            assert_ne!(other, a);
+        }
+    }
+}
@@ -0,0 +1,684 @@
+//! Morphs HTML pages as they stream through the server.
+//!
+//! Web servers (e.g., nginx body filters) receive a response's body in
+//! chunks, and may have to send its length before its body. `HtmlStream`
+//! decides the page's target size up front, from its (known or estimated)
+//! length, rewrites the references in each chunk as it comes, and appends
+//! the references to padding objects and the padding at the end.
+//!
+//! Unlike `morph_html`, it only sees the page a chunk at a time: it carries
+//! no decisions to the documents the page references (which are morphed on
+//! their own when requested), and the target size only leaves room for
+//! rewriting as many references as the page was sampled to have objects. A
+//! page larger than its estimated length, or with more references than the
+//! target size leaves room for, cannot be morphed to its target size, and
+//! is refused.
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+use auth::{get_auth_params_bound, protect_params, ParamAuth};
+use distribution::{PageSample, SamplingConfig};
+use endpoint::{get_padding_kind, get_padding_path_bound, get_padding_path_within};
+use graph::{Node, PageGraph};
+use lookup::ObjectLookup;
+use morphing::{get_size_bounds, insert_objects_refs, MAX_SIZE_DIGITS};
+use objects::*;
+use pad::{can_pad, get_padding, min_padding_size};
+use parsing::{parse_objects, parse_request_uri, resolve_uri, PADDING_PARAM};
+use placement::{get_padding_ref, Placement};
+
+// Elements which are held back until they are complete, as (start, end):
+// those whose content is not HTML, and those whose references depend on
+// one another.
+static HTML_HELD_ELEMENTS: &[(&[u8], &[u8])] = &[
+    (b"<!--", b"-->"),
+    (b"<script", b"</script"),
+    (b"<style", b"</style"),
+    (b"<textarea", b"</textarea"),
+    (b"<title", b"</title"),
+    (b"<picture", b"</picture"),
+];
+
+/// An HTML page being morphed as it streams through the server.
+pub struct HtmlStream<'a, R: Rng, L: ObjectLookup + 'a> {
+    rng: R,
+    lookup: &'a L,
+    auth: Option<&'a ParamAuth>,
+    page: PageSample<'a>,
+    /// The objects referenced so far; the first node is the page itself.
+    /// References to objects without a target size (nested pages) are left
+    /// as they are.
+    graph: PageGraph,
+    indices: HashMap<String, usize>,
+    /// Number of objects fetched among those referenced so far.
+    object_count: usize,
+    /// Upper bound to the length of the paths of padding objects, which
+    /// the target size leaves room for.
+    padding_path_bound: usize,
+    /// The page's (estimated) size before morphing.
+    size: usize,
+    /// Bytes of the page received so far.
+    received: usize,
+    target_size: usize,
+    /// Bytes added to the page so far.
+    growth: usize,
+    /// Bytes of the page received, but not morphed yet.
+    buffer: Vec<u8>,
+}
+
+impl<'a, R: Rng, L: ObjectLookup> HtmlStream<'a, R, L> {
+    /// Construct an HtmlStream morphing the page requested by `request`,
+    /// whose size is `size`, or at most `size` if it is estimated.
+    ///
+    /// The page's target size is sampled along with its number of objects,
+    /// and leaves room for rewriting that many references.
+    ///
+    /// # Arguments
+    ///
+    /// `rng` - Random number generator.
+    /// `request` - HTTP request for the page.
+    /// `size` - Size of the page, or an upper bound to it.
+    /// `lookup` - Lookup for the objects referenced by the page.
+    /// `config` - Distributions to sample the page from.
+    /// `auth` - Authenticates the parameters of objects' references, if any.
+    pub fn new(
+        mut rng: R,
+        request: &str,
+        size: usize,
+        lookup: &'a L,
+        config: &'a SamplingConfig,
+        auth: Option<&'a ParamAuth>,
+    ) -> HtmlStream<'a, R, L> {
+        let path = resolve_uri("/", parse_request_uri(request)).unwrap_or_else(|| "/".to_string());
+        let graph = PageGraph {
+            nodes: vec![Node {
+                object: Object {
+                    kind: ObjectKind::HTML,
+                    content: Vec::new(),
+                    size,
+                    uri: Some(path.clone()),
+                    position: None,
+                    target_size: None,
+                    candidate_set: None,
+                },
+                path,
+                parent: None,
+                refs: Vec::new(),
+            }],
+            candidate_sets: Vec::new(),
+        };
+
+        // Each object takes either a rewritten reference, or a reference to
+        // a padding object, which is the longest.
+        let page = config.sample_page(&mut rng, 0);
+        let params_len = get_padding_params_bound(auth);
+        // Padding objects are modelled on objects in directories no longer
+        // than the page's, whatever the page references.
+        let padding_path_bound = get_padding_path_bound(&graph);
+        let uri = "x".repeat(padding_path_bound + 1 + params_len);
+        let ref_len =
+            get_padding_ref(ObjectKind::HTML, &uri, Placement::BodyEnd).map_or(0, |r| r.len());
+        let growth_bound = page.object_count * ref_len;
+        let target_size = page.sample_html_size(&mut rng, size + growth_bound, usize::MAX);
+
+        HtmlStream {
+            rng,
+            lookup,
+            auth,
+            page,
+            graph,
+            indices: HashMap::new(),
+            object_count: 0,
+            padding_path_bound,
+            size,
+            received: 0,
+            target_size,
+            growth: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Returns the size the page is padded to, which the server may send
+    /// before the page.
+    pub fn target_size(&self) -> usize {
+        self.target_size
+    }
+
+    /// Morphs the next chunk of the page.
+    ///
+    /// # Returns
+    /// The morphed part of the page, which may be shorter than the chunk:
+    /// the end of the chunk is held back until the elements it starts are
+    /// complete. Err if the page cannot be morphed to its target size, as it
+    /// is larger than the size it was estimated to have, or its references
+    /// do not fit in the target size.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<u8>, ()> {
+        self.received += chunk.len();
+        if self.received > self.size {
+            return Err(());
+        }
+        self.buffer.extend(chunk);
+        let end = get_complete_len(&self.buffer);
+        let fragment = self.buffer.drain(..end).collect();
+        self.morph_fragment(fragment)
+    }
+
+    /// Morphs the rest of the page, and pads it to its target size.
+    ///
+    /// # Returns
+    /// The rest of the morphed page, followed by the references to padding
+    /// objects, and the padding; or Err if the page cannot be morphed to its
+    /// target size, as it is larger than the size it was estimated to have,
+    /// or its references do not fit in the target size. A server which
+    /// already sent the target size as the response's length should then
+    /// abort the response.
+    pub fn finish(mut self) -> Result<Vec<u8>, ()> {
+        if self.received > self.size {
+            return Err(());
+        }
+        let fragment = self.buffer.split_off(0);
+        let mut tail = self.morph_fragment(fragment)?;
+
+        while self.object_count < self.page.object_count {
+            let kind = get_padding_kind(ObjectKind::HTML);
+            let target_size = self.page.sample_object_size(&mut self.rng, kind, 1);
+            let path =
+                get_padding_path_within(&mut self.rng, &self.graph, 0, self.padding_path_bound);
+            let params = format!("{}={}", PADDING_PARAM, target_size);
+            let uri = format!("{}?{}", path, protect_params(&path, params, self.auth));
+            let padding_ref =
+                get_padding_ref(ObjectKind::HTML, &uri, Placement::BodyEnd).ok_or(())?;
+            if padding_ref.len() > self.get_room() {
+                return Err(());
+            }
+            self.graph.add_padding(0, path, target_size);
+            self.growth += padding_ref.len();
+            self.object_count += 1;
+            tail.extend(padding_ref.into_bytes());
+        }
+
+        let size = self.received + self.growth;
+        if !can_pad(&ObjectKind::HTML, size, self.target_size) {
+            return Err(());
+        }
+        if self.target_size > size {
+            let pad_len = self.target_size - size;
+            tail.extend(get_padding(&mut self.rng, &ObjectKind::HTML, pad_len));
+        }
+        Ok(tail)
+    }
+
+    /// Returns the room the target size leaves for growing the page, besides
+    /// the padding's syntax.
+    fn get_room(&self) -> usize {
+        self.target_size
+            .saturating_sub(self.size + self.growth + min_padding_size(&ObjectKind::HTML))
+    }
+
+    /// Rewrites the references in a fragment of the page, which contains no
+    /// incomplete element.
+    fn morph_fragment(&mut self, content: Vec<u8>) -> Result<Vec<u8>, ()> {
+        let mut fragment = Object {
+            kind: ObjectKind::HTML,
+            size: content.len(),
+            content,
+            uri: None,
+            position: None,
+            target_size: None,
+            candidate_set: None,
+        };
+        let references = parse_objects(&fragment);
+
+        let mut candidate_sets = self.get_candidate_sets(&references);
+        // Candidate sets of the fragment which an object was counted for.
+        let mut fetched_sets = HashSet::new();
+        let mut refs = Vec::new();
+        for reference in &references {
+            let path = match self.get_path(reference) {
+                Some(path) => path,
+                None => continue,
+            };
+            let i = match self.indices.get(&path) {
+                Some(&i) => i,
+                None => match self.add_object(reference, path.clone(), &mut candidate_sets) {
+                    Some(i) => {
+                        // The browser fetches a single object among a
+                        // candidate set.
+                        if reference
+                            .candidate_set
+                            .map_or(true, |set| fetched_sets.insert(set))
+                        {
+                            self.object_count += 1;
+                        }
+                        i
+                    }
+                    None => continue,
+                },
+            };
+            let target_size = match self.graph.nodes[i].object.target_size {
+                Some(target_size) => target_size,
+                None => continue,
+            };
+
+            let params = format!("{}={}", PADDING_PARAM, target_size);
+            let params = protect_params(&path, params, self.auth);
+            // Preceded by '?' or '&'.
+            if 1 + params.len() > self.get_room() {
+                return Err(());
+            }
+            self.growth += 1 + params.len();
+            refs.push((reference, params));
+        }
+
+        insert_objects_refs(&mut self.rng, &mut fragment, &refs, Placement::BodyEnd)?;
+        Ok(fragment.content)
+    }
+
+    /// Returns the path of the object referenced by `reference`, if any.
+    fn get_path(&self, reference: &Object) -> Option<String> {
+        resolve_uri(&self.graph.nodes[0].path, reference.uri.as_ref()?)
+    }
+
+    /// Returns the candidate sets of a fragment, with the size their shared
+    /// target size must reach to fit each of their candidates to add to the
+    /// graph, and the target size of a candidate added before if it does.
+    fn get_candidate_sets(&self, references: &[Object]) -> HashMap<usize, CandidateSet> {
+        let mut candidate_sets: HashMap<usize, CandidateSet> = HashMap::new();
+        // Target sizes of the candidates added before.
+        let mut added: HashMap<usize, Vec<usize>> = HashMap::new();
+        for reference in references {
+            let set = match reference.candidate_set {
+                Some(set) => set,
+                None => continue,
+            };
+            let path = match self.get_path(reference) {
+                Some(path) => path,
+                None => continue,
+            };
+            if let Some(&i) = self.indices.get(&path) {
+                if let Some(target_size) = self.graph.nodes[i].object.target_size {
+                    added.entry(set).or_default().push(target_size);
+                }
+                continue;
+            }
+            let kind = reference.kind;
+            let size = match self.lookup.object_size(&path) {
+                Some(size) if kind != ObjectKind::HTML => size,
+                _ => continue,
+            };
+            let bound = self.get_size_bound(&path, kind, size) + min_padding_size(&kind);
+            let candidate_set = candidate_sets.entry(set).or_insert(CandidateSet {
+                bound: 0,
+                target_size: None,
+            });
+            candidate_set.bound = candidate_set.bound.max(bound);
+        }
+
+        for (set, candidate_set) in &mut candidate_sets {
+            candidate_set.target_size = added.get(set).and_then(|target_sizes| {
+                target_sizes
+                    .iter()
+                    .cloned()
+                    .find(|&target_size| target_size >= candidate_set.bound)
+            });
+        }
+        candidate_sets
+    }
+
+    /// Adds the object referenced by `reference` at `path` to the graph,
+    /// and samples its target size, or that of its candidate set, which
+    /// its other candidates share.
+    ///
+    /// # Returns
+    /// The node of the object, or None if it cannot be looked up.
+    fn add_object(
+        &mut self,
+        reference: &Object,
+        path: String,
+        candidate_sets: &mut HashMap<usize, CandidateSet>,
+    ) -> Option<usize> {
+        let size = self.lookup.object_size(&path)?;
+        let kind = reference.kind;
+        let candidate_set = reference
+            .candidate_set
+            .and_then(|set| candidate_sets.get_mut(&set));
+        // Pages nested in the page are morphed on their own when requested.
+        let target_size = match (kind, candidate_set) {
+            (ObjectKind::HTML, _) => None,
+            (_, Some(candidate_set)) => {
+                if candidate_set.target_size.is_none() {
+                    // At least the bound of this candidate and its padding.
+                    let bound = candidate_set.bound - min_padding_size(&kind);
+                    let target_size = self.page.sample_object_size(&mut self.rng, kind, bound);
+                    candidate_set.target_size = Some(target_size);
+                }
+                candidate_set.target_size
+            }
+            (_, None) => {
+                let bound = self.get_size_bound(&path, kind, size);
+                Some(self.page.sample_object_size(&mut self.rng, kind, bound))
+            }
+        };
+
+        let i = self.graph.nodes.len();
+        self.graph.nodes.push(Node {
+            object: Object {
+                kind,
+                content: Vec::new(),
+                size,
+                uri: Some(path.clone()),
+                position: None,
+                target_size,
+                candidate_set: reference.candidate_set,
+            },
+            path: path.clone(),
+            parent: Some(0),
+            refs: Vec::new(),
+        });
+        self.indices.insert(path, i);
+        Some(i)
+    }
+
+    /// Returns an upper bound to the size of the object at `path` once
+    /// morphed, accounting for the references in stylesheets.
+    fn get_size_bound(&self, path: &str, kind: ObjectKind, size: usize) -> usize {
+        if kind != ObjectKind::CSS {
+            return size;
+        }
+        let css = Object {
+            kind,
+            content: self.lookup.object_content(path).unwrap_or_default(),
+            size,
+            uri: Some(path.to_string()),
+            position: None,
+            target_size: None,
+            candidate_set: None,
+        };
+        let graph = PageGraph::build(&css, self.lookup);
+        get_size_bounds(&graph, self.auth)[0].max(size)
+    }
+}
+
+// A candidate set of a fragment of the page.
+struct CandidateSet {
+    /// Size the target size must reach to fit the candidates to add.
+    bound: usize,
+    /// Target size of the candidates, once sampled or taken from a
+    /// candidate added before.
+    target_size: Option<usize>,
+}
+
+/// Returns an upper bound to the length of the parameters of a reference,
+/// as authenticated with `auth`, if any.
+fn get_padding_params_bound(auth: Option<&ParamAuth>) -> usize {
+    // "alpaca-padding=" followed by the target size.
+    get_auth_params_bound(auth, 1 + PADDING_PARAM.len() + MAX_SIZE_DIGITS)
+}
+
+/// Returns the length of the longest prefix of the HTML which contains no
+/// incomplete tag, nor any element held back until it is complete.
+fn get_complete_len(html: &[u8]) -> usize {
+    let html = html.to_ascii_lowercase();
+    let mut end = match rfind(&html, b"<") {
+        Some(lt) if find(&html[lt..], b">").is_none() => lt,
+        _ => html.len(),
+    };
+    loop {
+        let held = HTML_HELD_ELEMENTS
+            .iter()
+            .filter_map(|&(start, close)| {
+                let i = rfind(&html[..end], start)?;
+                match find(&html[i..end], close) {
+                    Some(_) => None,
+                    None => Some(i),
+                }
+            })
+            .min();
+        match held {
+            Some(i) => end = i,
+            None => return end,
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    use std::time::Duration;
+
+    use auth::Unauthenticated;
+    use distribution::Distribution;
+    use parsing::parse_target_size;
+
+    static HTML: &str = "<html><head><title>a < b</title>\
+                         <link rel=\"stylesheet\" href=\"css/style.css\">\
+                         <script>if (a < b) {}</script></head><body>\
+                         <img src=\"/img/a.png\"><!-- <img src=\"/img/b.png\"> -->\
+                         <picture><source srcset=\"img/b.png\"><img src=\"img/a.png\"></picture>\
+                         <iframe src=\"frame.html\"></iframe>\
+                         <img src=\"https://example.com/x.png\"></body></html>";
+
+    fn generate_lookup() -> HashMap<String, Vec<u8>> {
+        let mut lookup = HashMap::new();
+        lookup.insert(
+            "/css/style.css".to_string(),
+            b"body { background: url(../img/b.png); }".to_vec(),
+        );
+        lookup.insert("/img/a.png".to_string(), vec![0u8; 1000]);
+        lookup.insert("/img/b.png".to_string(), vec![0u8; 3000]);
+        lookup.insert("/frame.html".to_string(), b"<p>frame</p>".to_vec());
+        lookup
+    }
+
+    fn morph(chunk_size: usize, size: usize, auth: Option<&ParamAuth>) -> (Vec<u8>, usize) {
+        let lookup = generate_lookup();
+        let config = SamplingConfig::default();
+        let rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let mut stream = HtmlStream::new(
+            rng,
+            "GET /index.html HTTP/1.1",
+            size,
+            &lookup,
+            &config,
+            auth,
+        );
+        let mut morphed = Vec::new();
+        for chunk in HTML.as_bytes().chunks(chunk_size) {
+            morphed.extend(stream.push(chunk).unwrap());
+        }
+        let target_size = stream.target_size();
+        morphed.extend(stream.finish().unwrap());
+        (morphed, target_size)
+    }
+
+    #[test]
+    fn test_html_stream() {
+        let (morphed, target_size) = morph(HTML.len(), HTML.len(), None);
+        assert_eq!(morphed.len(), target_size);
+
+        // The page is morphed alike, whatever its chunks.
+        for &chunk_size in &[1, 7, 64] {
+            assert_eq!(
+                morph(chunk_size, HTML.len(), None),
+                (morphed.clone(), target_size)
+            );
+        }
+
+        let page = Object::from(&morphed, "GET /index.html HTTP/1.1");
+        let refs = parse_objects(&page)
+            .into_iter()
+            .map(|o| o.uri.unwrap())
+            .collect::<Vec<_>>();
+        // An object has the same target size wherever it is referenced, at
+        // least its size once morphed.
+        let style_size = parse_target_size(&refs[0]).unwrap();
+        assert!(style_size >= 39 + 36);
+        let a_size = parse_target_size(&refs[1]).unwrap();
+        assert!(a_size >= 1000);
+        assert_eq!(refs[2].split('?').next(), Some("img/b.png"));
+        assert!(parse_target_size(&refs[2]).unwrap() >= 3000);
+        assert_eq!(refs[3], refs[1].replacen('/', "", 1));
+        // Nested pages, commented and external objects are left as they are.
+        assert_eq!(refs[4], "frame.html");
+        assert!(morphed.windows(17).any(|w| w == b"<img src=\"/img/b."));
+        assert!(morphed
+            .windows(23)
+            .any(|w| w == b"\"https://example.com/x."));
+        // Padding objects are referenced at the end.
+        assert!(refs[5..].iter().all(|r| parse_target_size(r).is_some()));
+        assert!(morphed.ends_with(b"-->"));
+    }
+
+    #[test]
+    fn test_html_stream_estimated_size() {
+        // A page smaller than estimated is padded to its target size too.
+        let (morphed, target_size) = morph(10, HTML.len() + 1000, None);
+        assert_eq!(morphed.len(), target_size);
+        assert!(target_size >= HTML.len() + 1000);
+    }
+
+    #[test]
+    fn test_html_stream_underestimated_size() {
+        let lookup = generate_lookup();
+        let config = SamplingConfig::default();
+        let rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let size = HTML.len() - 10;
+        let mut stream = HtmlStream::new(
+            rng,
+            "GET /index.html HTTP/1.1",
+            size,
+            &lookup,
+            &config,
+            None,
+        );
+        assert!(stream.push(&HTML.as_bytes()[..size]).is_ok());
+        // The page turns out larger than its target size leaves room for.
+        assert_eq!(stream.push(&HTML.as_bytes()[size..]), Err(()));
+        assert_eq!(stream.finish(), Err(()));
+    }
+
+    #[test]
+    fn test_html_stream_too_many_references() {
+        let lookup = generate_lookup();
+        let config = SamplingConfig::default();
+        let rng = XorShiftRng::from_seed([0, 1, 2, 3]);
+        let html = "<img src=\"/img/a.png\">".repeat(10000);
+        let mut stream = HtmlStream::new(
+            rng,
+            "GET /index.html HTTP/1.1",
+            html.len(),
+            &lookup,
+            &config,
+            None,
+        );
+        // The references outgrow the room left by the target size: rather
+        // than leaving some of them unchanged, the stream fails.
+        assert!(html
+            .as_bytes()
+            .chunks(1000)
+            .any(|chunk| stream.push(chunk).is_err()));
+    }
+
+    #[test]
+    fn test_html_stream_padding_paths() {
+        let mut lookup = generate_lookup();
+        let directory = format!("/{}/", "d".repeat(10000));
+        lookup.insert(format!("{}x.png", directory), vec![0u8; 10]);
+        // Pages have about 20 objects.
+        let config = SamplingConfig {
+            object_count: Distribution::LogNormal {
+                mean: 3.0,
+                std_dev: 0.1,
+            },
+            ..SamplingConfig::default()
+        };
+        let html = format!("<img src=\"{}x.png\">", directory);
+        for seed in 0..5 {
+            let rng = XorShiftRng::from_seed([seed, 1, 2, 3]);
+            let mut stream = HtmlStream::new(
+                rng,
+                "GET /index.html HTTP/1.1",
+                html.len(),
+                &lookup,
+                &config,
+                None,
+            );
+            let mut morphed = stream.push(html.as_bytes()).unwrap();
+            let target_size = stream.target_size();
+            // Padding objects are not modelled on the image, whose directory
+            // is longer than the target size leaves room for.
+            morphed.extend(stream.finish().unwrap());
+            assert_eq!(morphed.len(), target_size);
+            let page = Object::from(&morphed, "GET /index.html HTTP/1.1");
+            let refs = parse_objects(&page);
+            assert!(refs[1..]
+                .iter()
+                .all(|r| !r.uri.as_ref().unwrap().starts_with(&directory)));
+        }
+    }
+
+    #[test]
+    fn test_html_stream_candidate_sets() {
+        let lookup = generate_lookup();
+        let config = SamplingConfig::default();
+        // The smaller candidate comes first.
+        let html = "<picture><source srcset=\"/img/a.png\"><img src=\"/img/b.png\"></picture>";
+        for seed in 0..20 {
+            let rng = XorShiftRng::from_seed([seed, 1, 2, 3]);
+            let mut stream = HtmlStream::new(
+                rng,
+                "GET /index.html HTTP/1.1",
+                html.len(),
+                &lookup,
+                &config,
+                None,
+            );
+            let morphed = stream.push(html.as_bytes()).unwrap();
+            let page = Object::from(&morphed, "GET /index.html HTTP/1.1");
+            let sizes = parse_objects(&page)
+                .into_iter()
+                .map(|o| parse_target_size(&o.uri.unwrap()).unwrap())
+                .collect::<Vec<_>>();
+            // Both candidates share a target size, which fits the larger.
+            assert_eq!(sizes.len(), 2);
+            assert_eq!(sizes[0], sizes[1]);
+            assert!(sizes[0] >= 3000);
+        }
+    }
+
+    #[test]
+    fn test_html_stream_authenticated() {
+        let auth = ParamAuth::new(b"secret", Duration::from_secs(60), Unauthenticated::Refuse);
+        let (morphed, target_size) = morph(16, HTML.len(), Some(&auth));
+        assert_eq!(morphed.len(), target_size);
+        let page = Object::from(&morphed, "GET /index.html HTTP/1.1");
+        let verified = parse_objects(&page)
+            .into_iter()
+            .filter_map(|o| resolve_uri("/index.html", &o.uri.unwrap()))
+            .filter(|uri| auth.verify(uri))
+            .count();
+        // All but the nested page.
+        assert_eq!(verified, parse_objects(&page).len() - 1);
+    }
+
+    #[test]
+    fn test_get_complete_len() {
+        assert_eq!(get_complete_len(b"<p>a</p>"), 8);
+        assert_eq!(get_complete_len(b"<p>a<img src=\"x"), 4);
+        assert_eq!(get_complete_len(b"<p><SCRIPT>a<b"), 3);
+        assert_eq!(get_complete_len(b"<p><script>a<b</script>"), 23);
+        assert_eq!(get_complete_len(b"<p><!-- <script></script>"), 3);
+        assert_eq!(get_complete_len(b"<picture><img src=a.png>"), 0);
+        assert_eq!(get_complete_len(b"<picture><img src=a.png></picture>"), 34);
+    }
+}